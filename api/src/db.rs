@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use log::error;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, Config, NoTls};
+
+/// A read-only connection to the database, reopened when it's lost. Queries
+/// are pipelined over the one connection, so requests don't wait on each
+/// other.
+#[derive(Debug)]
+pub struct Db {
+    config: Config,
+    client: Mutex<Arc<Client>>,
+}
+
+impl Db {
+    /// Connects to the database at `url`, which should log in as the
+    /// `reader` role.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let config: Config = url
+            .parse()
+            .context("Invalid READER_DATABASE_URL environment variable")?;
+
+        Db::with_config(config).await
+    }
+
+    /// Connects to the database `config` describes.
+    pub async fn with_config(mut config: Config) -> Result<Self> {
+        if config.get_application_name().is_none() {
+            config.application_name("distance-db-api");
+        }
+        let client = connect(&config)
+            .await
+            .context("Couldn't connect to the database")?;
+
+        Ok(Db {
+            config,
+            client: Mutex::new(Arc::new(client)),
+        })
+    }
+
+    /// Returns the connection, reconnecting first if it was closed.
+    pub async fn client(&self) -> Result<Arc<Client>, tokio_postgres::Error> {
+        let mut client = self.client.lock().await;
+        if client.is_closed() {
+            *client = Arc::new(connect(&self.config).await?);
+        }
+
+        Ok(Arc::clone(&client))
+    }
+}
+
+async fn connect(config: &Config) -> Result<Client, tokio_postgres::Error> {
+    let (client, connection) = config.connect(NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            error!("Database connection error: {e}");
+        }
+    });
+
+    // The reader role can't write anyway, but this keeps the API read-only
+    // even if it's given other credentials
+    client
+        .batch_execute("SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY")
+        .await?;
+
+    Ok(client)
+}
@@ -0,0 +1,56 @@
+#![warn(
+    rust_2018_idioms,
+    deprecated_in_future,
+    macro_use_extern_crate,
+    missing_debug_implementations,
+    unused_qualifications
+)]
+
+use crate::db::Db;
+use anyhow::{Context, Result};
+use log::{error, info};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::{env, process};
+use tokio::net::TcpListener;
+
+mod db;
+mod routes;
+
+const DEFAULT_API_ADDRESS: &str = "127.0.0.1:9466";
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    color_backtrace::install();
+    dotenv::dotenv().ok();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    if let Err(e) = run().await {
+        error!("error: {e}");
+        for cause in e.chain().skip(1) {
+            error!(" caused by: {cause}");
+        }
+
+        process::exit(-1);
+    }
+}
+
+async fn run() -> Result<()> {
+    let address: SocketAddr = env::var("API_ADDRESS")
+        .unwrap_or_else(|_| DEFAULT_API_ADDRESS.to_owned())
+        .parse()
+        .context("Invalid API_ADDRESS environment variable")?;
+    let database_url = env::var("READER_DATABASE_URL")
+        .context("Environment variable READER_DATABASE_URL is not set")?;
+
+    let db = Db::connect(&database_url).await?;
+    let app = routes::router(Arc::new(db));
+
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Couldn't bind the API to {address}"))?;
+    info!("Serving the API on http://{address}");
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
@@ -0,0 +1,737 @@
+use crate::db::Db;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+use tokio_postgres::Row;
+use tokio_postgres::error::SqlState;
+
+/// The number of items a page holds unless `limit` asks for another number.
+const DEFAULT_LIMIT: i64 = 100;
+/// The most items a page can hold.
+const MAX_LIMIT: i64 = 1000;
+
+/// The routes of the API. Steam IDs are returned as strings, since they don't
+/// fit in a JavaScript number.
+pub fn router(db: Arc<Db>) -> Router {
+    Router::new()
+        .route("/levels", get(levels))
+        .route("/levels/{id}", get(level))
+        .route("/levels/{id}/{mode}", get(leaderboard))
+        .route("/players/{steam_id}", get(player))
+        .route("/users", get(search_users))
+        .route("/metadata", get(metadata))
+        .with_state(db)
+}
+
+/// An error response, as `{"error": <message>}`.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        ApiError {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for ApiError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        // The reader role's statement timeout cancels slow queries
+        if e.code() == Some(&SqlState::QUERY_CANCELED) {
+            return ApiError {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                message: "the query took too long".to_owned(),
+            };
+        }
+
+        error!("Database error: {e}");
+        ApiError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: "database error".to_owned(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    Sprint,
+    Challenge,
+    Stunt,
+}
+
+impl Mode {
+    fn as_str(self) -> &'static str {
+        match self {
+            Mode::Sprint => "sprint",
+            Mode::Challenge => "challenge",
+            Mode::Stunt => "stunt",
+        }
+    }
+
+    /// The column of the mode's leaderboard table that entries are ranked by.
+    fn value_column(self) -> &'static str {
+        match self {
+            Mode::Sprint | Mode::Challenge => "time",
+            Mode::Stunt => "score",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Official,
+    Workshop,
+}
+
+/// Returns the `LIMIT` and `OFFSET` of a page.
+fn page(limit: Option<i64>, offset: Option<i64>) -> Result<(i64, i64), ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    let offset = offset.unwrap_or(0);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {MAX_LIMIT}"
+        )));
+    }
+    if offset < 0 {
+        return Err(ApiError::bad_request("offset can't be negative"));
+    }
+
+    Ok((limit, offset))
+}
+
+fn steam_id_string(steam_id: i64) -> String {
+    (steam_id as u64).to_string()
+}
+
+#[derive(Debug, Deserialize)]
+struct LevelFilters {
+    /// Only levels with a leaderboard in this mode
+    mode: Option<Mode>,
+    /// Only official or only workshop levels
+    kind: Option<Kind>,
+    /// Only workshop levels by this author
+    author: Option<u64>,
+    /// Only workshop levels with this tag
+    tag: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Levels {
+    total: i64,
+    levels: Vec<Level>,
+}
+
+#[derive(Debug, Serialize)]
+struct Level {
+    id: i64,
+    name: String,
+    is_sprint: bool,
+    is_challenge: bool,
+    is_stunt: bool,
+    is_workshop: bool,
+    author_steam_id: Option<String>,
+    tags: Vec<String>,
+}
+
+impl Level {
+    fn from_row(row: &Row) -> Self {
+        Level {
+            id: row.get("id"),
+            name: row.get("name"),
+            is_sprint: row.get("is_sprint"),
+            is_challenge: row.get("is_challenge"),
+            is_stunt: row.get("is_stunt"),
+            is_workshop: row.get("is_workshop"),
+            author_steam_id: row
+                .get::<_, Option<i64>>("author_steam_id")
+                .map(steam_id_string),
+            tags: row
+                .get::<_, Option<Vec<String>>>("tags")
+                .unwrap_or_default(),
+        }
+    }
+}
+
+const LEVEL_COLUMNS: &str = "l.id, l.name, l.is_sprint, l.is_challenge, l.is_stunt, d.level_id IS NOT NULL AS is_workshop, d.author_steam_id, d.tags";
+
+/// The filters of `GET /levels`, with the mode, kind, author and tag as
+/// parameters `$1` to `$4`. Each is ignored if null.
+const LEVEL_FILTERS: &str = "
+    FROM levels l LEFT JOIN workshop_level_details d ON d.level_id = l.id
+    WHERE ($1::text IS NULL
+            OR ($1 = 'sprint' AND l.is_sprint)
+            OR ($1 = 'challenge' AND l.is_challenge)
+            OR ($1 = 'stunt' AND l.is_stunt))
+        AND ($2::boolean IS NULL OR (d.level_id IS NOT NULL) = $2)
+        AND ($3::bigint IS NULL OR d.author_steam_id = $3)
+        AND ($4::text IS NULL OR $4 = ANY (d.tags))";
+
+/// Lists levels, official ones first, optionally filtered.
+async fn levels(
+    State(db): State<Arc<Db>>,
+    Query(filters): Query<LevelFilters>,
+) -> ApiResult<Levels> {
+    let (limit, offset) = page(filters.limit, filters.offset)?;
+    let mode = filters.mode.map(Mode::as_str);
+    let is_workshop = filters.kind.map(|kind| matches!(kind, Kind::Workshop));
+    let author = filters.author.map(|steam_id| steam_id as i64);
+
+    let db = db.client().await?;
+    let total = db
+        .query_one(
+            &format!("SELECT count(*) {LEVEL_FILTERS}"),
+            &[&mode, &is_workshop, &author, &filters.tag],
+        )
+        .await?
+        .get(0);
+    let levels = db
+        .query(
+            &format!(
+                "SELECT {LEVEL_COLUMNS} {LEVEL_FILTERS} ORDER BY d.level_id IS NOT NULL, abs(l.id) LIMIT $5 OFFSET $6"
+            ),
+            &[&mode, &is_workshop, &author, &filters.tag, &limit, &offset],
+        )
+        .await?
+        .iter()
+        .map(Level::from_row)
+        .collect();
+
+    Ok(Json(Levels { total, levels }))
+}
+
+async fn find_level(db: &tokio_postgres::Client, id: i64) -> Result<Level, ApiError> {
+    let row = db
+        .query_opt(
+            &format!(
+                "SELECT {LEVEL_COLUMNS} FROM levels l LEFT JOIN workshop_level_details d ON d.level_id = l.id WHERE l.id = $1"
+            ),
+            &[&id],
+        )
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("no level with ID {id}")))?;
+
+    Ok(Level::from_row(&row))
+}
+
+async fn level(State(db): State<Arc<Db>>, Path(id): Path<i64>) -> ApiResult<Level> {
+    let db = db.client().await?;
+
+    Ok(Json(find_level(&db, id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Leaderboard {
+    level_id: i64,
+    level_name: String,
+    mode: Mode,
+    total: i64,
+    entries: Vec<Entry>,
+}
+
+/// A leaderboard entry. Sprint and challenge entries have a `time` in
+/// milliseconds, and stunt entries a `score`.
+#[derive(Debug, Serialize)]
+struct Entry {
+    rank: i32,
+    steam_id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<i32>,
+    has_replay: bool,
+}
+
+/// Returns the `time` and `score` of an entry of `mode` worth `value`.
+fn time_and_score(mode: Mode, value: i32) -> (Option<i32>, Option<i32>) {
+    match mode {
+        Mode::Sprint | Mode::Challenge => (Some(value), None),
+        Mode::Stunt => (None, Some(value)),
+    }
+}
+
+/// A page of a level's leaderboard, best first, with the players' names.
+async fn leaderboard(
+    State(db): State<Arc<Db>>,
+    Path((id, mode)): Path<(i64, Mode)>,
+    Query(query): Query<PageQuery>,
+) -> ApiResult<Leaderboard> {
+    let (limit, offset) = page(query.limit, query.offset)?;
+    let db = db.client().await?;
+    let level = find_level(&db, id).await?;
+    let has_mode = match mode {
+        Mode::Sprint => level.is_sprint,
+        Mode::Challenge => level.is_challenge,
+        Mode::Stunt => level.is_stunt,
+    };
+    if !has_mode {
+        return Err(ApiError::not_found(format!(
+            "level {id} has no {} leaderboard",
+            mode.as_str()
+        )));
+    }
+
+    let table = format!("{}_leaderboard_entries", mode.as_str());
+    let total = db
+        .query_one(
+            &format!("SELECT count(*) FROM {table} WHERE level_id = $1"),
+            &[&id],
+        )
+        .await?
+        .get(0);
+    let entries = db
+        .query(
+            &format!(
+                "SELECT e.rank, e.steam_id, u.name, e.{}, e.has_replay FROM {table} e JOIN users u ON u.steam_id = e.steam_id WHERE e.level_id = $1 ORDER BY e.rank, e.steam_id LIMIT $2 OFFSET $3",
+                mode.value_column()
+            ),
+            &[&id, &limit, &offset],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            let (time, score) = time_and_score(mode, row.get(3));
+            Entry {
+                rank: row.get(0),
+                steam_id: steam_id_string(row.get(1)),
+                name: row.get(2),
+                time,
+                score,
+                has_replay: row.get(4),
+            }
+        })
+        .collect();
+
+    Ok(Json(Leaderboard {
+        level_id: level.id,
+        level_name: level.name,
+        mode,
+        total,
+        entries,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct Player {
+    steam_id: String,
+    name: String,
+    entries: Vec<PlayerEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct PlayerEntry {
+    level_id: i64,
+    level_name: String,
+    mode: Mode,
+    rank: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<i32>,
+    has_replay: bool,
+}
+
+/// A player's name and every entry of theirs, official levels first.
+async fn player(State(db): State<Arc<Db>>, Path(steam_id): Path<u64>) -> ApiResult<Player> {
+    let steam_id = steam_id as i64;
+    let db = db.client().await?;
+    let name = db
+        .query_opt("SELECT name FROM users WHERE steam_id = $1", &[&steam_id])
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("no player with Steam ID {steam_id}")))?
+        .get(0);
+
+    let entries = db
+        .query(
+            "SELECT e.level_id, l.name, e.mode, e.rank, e.value, e.has_replay
+            FROM (
+                SELECT level_id, 'sprint' AS mode, rank, time AS value, has_replay
+                FROM sprint_leaderboard_entries WHERE steam_id = $1
+                UNION ALL
+                SELECT level_id, 'challenge', rank, time, has_replay
+                FROM challenge_leaderboard_entries WHERE steam_id = $1
+                UNION ALL
+                SELECT level_id, 'stunt', rank, score, has_replay
+                FROM stunt_leaderboard_entries WHERE steam_id = $1
+            ) e
+            JOIN levels l ON l.id = e.level_id
+            ORDER BY
+                l.id IN (SELECT level_id FROM workshop_level_details),
+                abs(l.id),
+                array_position(ARRAY['sprint', 'challenge', 'stunt'], e.mode)",
+            &[&steam_id],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            let mode = match row.get::<_, &str>(2) {
+                "sprint" => Mode::Sprint,
+                "challenge" => Mode::Challenge,
+                _ => Mode::Stunt,
+            };
+            let (time, score) = time_and_score(mode, row.get(4));
+            PlayerEntry {
+                level_id: row.get(0),
+                level_name: row.get(1),
+                mode,
+                rank: row.get(3),
+                time,
+                score,
+                has_replay: row.get(5),
+            }
+        })
+        .collect();
+
+    Ok(Json(Player {
+        steam_id: steam_id_string(steam_id),
+        name,
+        entries,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct UserSearch {
+    /// Part of the names to find, case-insensitively
+    name: String,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Users {
+    users: Vec<User>,
+}
+
+#[derive(Debug, Serialize)]
+struct User {
+    steam_id: String,
+    name: String,
+}
+
+/// Finds players whose name contains the one searched for, exact matches
+/// first.
+async fn search_users(
+    State(db): State<Arc<Db>>,
+    Query(search): Query<UserSearch>,
+) -> ApiResult<Users> {
+    let (limit, offset) = page(search.limit, search.offset)?;
+    if search.name.is_empty() {
+        return Err(ApiError::bad_request("name can't be empty"));
+    }
+    let pattern = format!(
+        "%{}%",
+        search
+            .name
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+
+    let users = db
+        .client()
+        .await?
+        .query(
+            "SELECT steam_id, name FROM users WHERE name ILIKE $1 ORDER BY lower(name) <> lower($2), name, steam_id LIMIT $3 OFFSET $4",
+            &[&pattern, &search.name, &limit, &offset],
+        )
+        .await?
+        .iter()
+        .map(|row| User {
+            steam_id: steam_id_string(row.get(0)),
+            name: row.get(1),
+        })
+        .collect();
+
+    Ok(Json(Users { users }))
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    /// When the database was last updated by a populator run, in UTC.
+    last_updated: Option<String>,
+}
+
+async fn metadata(State(db): State<Arc<Db>>) -> ApiResult<Metadata> {
+    let last_updated = db
+        .client()
+        .await?
+        .query_opt(
+            "SELECT to_char(last_updated AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') FROM metadata",
+            &[],
+        )
+        .await?
+        .and_then(|row| row.get(0));
+
+    Ok(Json(Metadata { last_updated }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use distance_db_test_db::TestDb;
+    use serde_json::Value as JsonValue;
+    use tower::ServiceExt;
+
+    /// Two official levels and two workshop levels, with their players. Player
+    /// 1 has entries on all of them, and the other players' names need
+    /// escaping to be searched for.
+    const FIXTURES: &str = r#"
+        INSERT INTO users (steam_id, name) VALUES
+            (1, 'Alice'), (2, 'alice_'), (3, '100% Speed'), (4, 'Bob'),
+            (90, 'Sprint Author'), (91, 'Stunt Author');
+        INSERT INTO levels (id, name, is_sprint, is_challenge, is_stunt) VALUES
+            (-1000, 'Broken Symmetry', true, true, true),
+            (-2, 'Lost Society', true, false, false),
+            (10, 'Sprint Level', true, false, false),
+            (20, 'Stunt Level', false, false, true);
+        INSERT INTO workshop_level_details (level_id, raw_details, tags) VALUES
+            (10, '{"publishedfileid": "10", "creator": "90", "time_created": 0, "time_updated": 0}', ARRAY['Sprint', 'Advanced']),
+            (20, '{"publishedfileid": "20", "creator": "91", "time_created": 0, "time_updated": 0}', ARRAY['Stunt']);
+        INSERT INTO stunt_leaderboard_entries (level_id, steam_id, score, rank, has_replay) VALUES
+            (20, 1, 900, 1, false);
+        INSERT INTO challenge_leaderboard_entries (level_id, steam_id, time, rank, has_replay) VALUES
+            (-1000, 1, 30000, 1, true);
+        INSERT INTO sprint_leaderboard_entries (level_id, steam_id, time, rank, has_replay) VALUES
+            (10, 1, 5000, 1, false),
+            (-1000, 2, 10000, 1, false), (-1000, 1, 12000, 2, true),
+            (-1000, 3, 12000, 2, false), (-1000, 4, 15000, 4, false),
+            (-2, 1, 20000, 1, false);
+    "#;
+
+    /// Returns a database of its own for a test, filled with [`FIXTURES`], and
+    /// the API's routes over it.
+    async fn create() -> (TestDb, Router) {
+        let db = TestDb::create().await;
+        db.client.batch_execute(FIXTURES).await.unwrap();
+        let app = router(Arc::new(
+            Db::with_config(db.url.parse().unwrap()).await.unwrap(),
+        ));
+
+        (db, app)
+    }
+
+    /// Sends a `GET` request for `uri` to `app`, and returns the status and
+    /// JSON body of the response.
+    async fn get(app: &Router, uri: &str) -> (StatusCode, JsonValue) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// Returns the IDs of the levels `GET uri` lists, and their total.
+    async fn level_ids(app: &Router, uri: &str) -> (Vec<i64>, i64) {
+        let (status, body) = get(app, uri).await;
+        assert_eq!(status, StatusCode::OK, "{uri}: {body}");
+        let ids = body["levels"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|level| level["id"].as_i64().unwrap())
+            .collect();
+
+        (ids, body["total"].as_i64().unwrap())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn levels_are_filtered() {
+        let (db, app) = create().await;
+
+        assert_eq!(
+            level_ids(&app, "/levels").await,
+            (vec![-2, -1000, 10, 20], 4)
+        );
+        assert_eq!(
+            level_ids(&app, "/levels?mode=stunt").await,
+            (vec![-1000, 20], 2)
+        );
+        assert_eq!(
+            level_ids(&app, "/levels?mode=challenge").await,
+            (vec![-1000], 1)
+        );
+        assert_eq!(
+            level_ids(&app, "/levels?kind=workshop").await,
+            (vec![10, 20], 2)
+        );
+        assert_eq!(
+            level_ids(&app, "/levels?kind=official&mode=sprint").await,
+            (vec![-2, -1000], 2)
+        );
+        assert_eq!(level_ids(&app, "/levels?author=90").await, (vec![10], 1));
+        assert_eq!(level_ids(&app, "/levels?tag=Stunt").await, (vec![20], 1));
+        assert_eq!(
+            level_ids(&app, "/levels?kind=official&tag=Stunt").await,
+            (vec![], 0)
+        );
+
+        db.destroy().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn pages_are_bounded() {
+        let (db, app) = create().await;
+
+        assert_eq!(
+            level_ids(&app, "/levels?limit=2&offset=1").await,
+            (vec![-1000, 10], 4)
+        );
+        assert_eq!(
+            level_ids(&app, &format!("/levels?limit={MAX_LIMIT}&offset=4")).await,
+            (vec![], 4)
+        );
+        for query in ["limit=0", &format!("limit={}", MAX_LIMIT + 1), "offset=-1"] {
+            let (status, body) = get(&app, &format!("/levels?{query}")).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+            assert!(body["error"].is_string());
+        }
+
+        // Tied entries keep their rank across pages
+        let (status, body) = get(&app, "/levels/-1000/sprint?limit=2&offset=1").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["total"], 4);
+        let entries = body["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            (&entries[0]["steam_id"], &entries[0]["rank"]),
+            (&json!("1"), &json!(2))
+        );
+        assert_eq!(
+            (&entries[1]["steam_id"], &entries[1]["rank"]),
+            (&json!("3"), &json!(2))
+        );
+
+        db.destroy().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn missing_leaderboards_are_not_found() {
+        let (db, app) = create().await;
+
+        let (status, body) = get(&app, "/levels/20/stunt").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["entries"][0]["score"], 900);
+        assert!(body["entries"][0].get("time").is_none());
+
+        let (status, body) = get(&app, "/levels/20/sprint").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "level 20 has no sprint leaderboard");
+
+        let (status, _) = get(&app, "/levels/30/sprint").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = get(&app, "/players/5").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        db.destroy().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn user_searches_match_wildcards_literally() {
+        let (db, app) = create().await;
+        let names = |body: &JsonValue| -> Vec<String> {
+            body["users"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|user| user["name"].as_str().unwrap().to_owned())
+                .collect()
+        };
+
+        let (_, body) = get(&app, "/users?name=%25").await;
+        assert_eq!(names(&body), ["100% Speed"]);
+        let (_, body) = get(&app, "/users?name=_").await;
+        assert_eq!(names(&body), ["alice_"]);
+        // Exact matches come first, regardless of case
+        let (_, body) = get(&app, "/users?name=ALICE_").await;
+        assert_eq!(names(&body), ["alice_"]);
+        let (_, body) = get(&app, "/users?name=alice").await;
+        assert_eq!(names(&body), ["Alice", "alice_"]);
+        let (_, body) = get(&app, "/users?name=author").await;
+        assert_eq!(names(&body), ["Sprint Author", "Stunt Author"]);
+
+        let (status, _) = get(&app, "/users?name=").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        db.destroy().await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn player_entries_are_ordered() {
+        let (db, app) = create().await;
+
+        let (status, body) = get(&app, "/players/1").await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        assert_eq!(body["name"], "Alice");
+        let entries: Vec<_> = body["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["level_id"].as_i64().unwrap(),
+                    entry["mode"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        // Official levels first, then by ID, then by mode
+        assert_eq!(
+            entries,
+            [
+                (-2, "sprint"),
+                (-1000, "sprint"),
+                (-1000, "challenge"),
+                (10, "sprint"),
+                (20, "stunt")
+            ]
+        );
+        assert_eq!(body["entries"][1]["rank"], 2);
+        assert_eq!(body["entries"][2]["time"], 30000);
+        assert_eq!(body["entries"][4]["score"], 900);
+
+        db.destroy().await;
+    }
+}
@@ -0,0 +1,224 @@
+//! Benchmarks `data_storing::run` loading synthetic datasets into a local
+//! Postgres, reporting the throughput of each of its phases separately.
+//!
+//! Requires `TEST_DATABASE_URL` to point to a Postgres server the benchmarks
+//! may create a database on, like the integration tests; they are skipped
+//! otherwise. Each dataset is stored in three scenarios:
+//!
+//! - `cold`: into empty tables
+//! - `unchanged`: over itself, so every leaderboard hash matches
+//! - `changed`: over a variant of itself with 10% of leaderboards changed
+
+use criterion::{
+    BenchmarkId, Criterion, SamplingMode, Throughput, criterion_group, criterion_main,
+};
+use distance_db_core::common::{DistanceData, RunOptions};
+use distance_db_core::data_storing;
+use distance_db_core::report::RunReport;
+use distance_db_core::synthetic::{self, SyntheticConfig};
+use distance_db_test_db::{connect, schema};
+use std::env;
+use std::time::Duration;
+use tokio_postgres::{Client, Config};
+
+const DATABASE_NAME: &str = "populator_bench";
+
+/// Datasets of increasing size, as `(name, workshop levels, users)`.
+const DATASETS: &[(&str, usize, usize)] = &[
+    ("small", 500, 2_000),
+    ("medium", 5_000, 20_000),
+    ("large", 20_000, 100_000),
+];
+
+/// The phases of `data_storing::run`, as named in its run report.
+const PHASES: &[&str] = &[
+    "store_users",
+    "store_levels",
+    "store_details",
+    "store_entries",
+];
+
+#[derive(Debug, Copy, Clone)]
+enum Scenario {
+    Cold,
+    Unchanged,
+    Changed,
+}
+
+impl Scenario {
+    fn name(self) -> &'static str {
+        match self {
+            Scenario::Cold => "cold",
+            Scenario::Unchanged => "unchanged",
+            Scenario::Changed => "changed",
+        }
+    }
+}
+
+fn storing(c: &mut Criterion) {
+    let Ok(url) = env::var("TEST_DATABASE_URL") else {
+        eprintln!("skipping: TEST_DATABASE_URL is not set");
+        return;
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut db = runtime.block_on(create_database(&url));
+
+    for &(name, workshop_levels, users) in DATASETS {
+        let mut data = synthetic::generate(&SyntheticConfig {
+            workshop_levels,
+            users,
+            ..SyntheticConfig::default()
+        });
+        let mut changed = with_changed_leaderboards(&data);
+
+        for scenario in [Scenario::Cold, Scenario::Unchanged, Scenario::Changed] {
+            // Whether the dataset has been stored for this scenario, which is
+            // put off so filtered out benchmarks don't store anything, and
+            // whether its variant is the one stored last
+            let mut prepared = false;
+            let mut changed_stored = false;
+
+            let mut group = c.benchmark_group(format!("{}/{name}", scenario.name()));
+            group
+                .sampling_mode(SamplingMode::Flat)
+                .sample_size(10)
+                .warm_up_time(Duration::from_secs(1));
+
+            for &phase in PHASES {
+                group.throughput(Throughput::Elements(phase_items(&data, phase)));
+                group.bench_function(BenchmarkId::from_parameter(phase), |b| {
+                    if !prepared {
+                        runtime.block_on(truncate(&db));
+                        runtime.block_on(run(&mut db, &mut data));
+                        prepared = true;
+                    }
+
+                    b.iter_custom(|iters| {
+                        let mut elapsed = Duration::ZERO;
+                        for _ in 0..iters {
+                            let report = match scenario {
+                                Scenario::Cold => {
+                                    runtime.block_on(truncate(&db));
+                                    runtime.block_on(run(&mut db, &mut data))
+                                }
+                                Scenario::Unchanged => runtime.block_on(run(&mut db, &mut data)),
+                                // Alternating between the dataset and its
+                                // variant changes the same leaderboards on
+                                // every run
+                                Scenario::Changed => {
+                                    changed_stored = !changed_stored;
+                                    let data = if changed_stored {
+                                        &mut changed
+                                    } else {
+                                        &mut data
+                                    };
+                                    runtime.block_on(run(&mut db, data))
+                                }
+                            };
+                            elapsed += phase_duration(&report, phase);
+                        }
+
+                        elapsed
+                    });
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+async fn truncate(db: &Client) {
+    db.batch_execute(
+        "TRUNCATE users, levels, workshop_level_details, sprint_leaderboard_entries, challenge_leaderboard_entries, stunt_leaderboard_entries, metadata",
+    )
+    .await
+    .unwrap();
+}
+
+async fn run(db: &mut Client, data: &mut DistanceData) -> RunReport {
+    let mut report = RunReport::new();
+    data_storing::run(db, data, RunOptions::default(), &mut report)
+        .await
+        .unwrap();
+
+    report
+}
+
+fn phase_duration(report: &RunReport, name: &str) -> Duration {
+    report
+        .phases
+        .iter()
+        .find(|phase| phase.name == name)
+        .map(|phase| Duration::from_secs_f64(phase.duration_secs))
+        .unwrap_or_default()
+}
+
+/// The number of items `phase` stores out of `data`.
+fn phase_items(data: &DistanceData, phase: &str) -> u64 {
+    let count = match phase {
+        "store_users" => data.users.len(),
+        "store_levels" => data.levels.len(),
+        "store_details" => data
+            .levels
+            .iter()
+            .filter(|level| level.workshop_level_details.is_some())
+            .count(),
+        _ => data
+            .levels
+            .iter()
+            .map(|l| l.sprint_entries.len() + l.challenge_entries.len() + l.stunt_entries.len())
+            .sum(),
+    };
+
+    count as u64
+}
+
+/// Returns a copy of `data` where every tenth non-empty leaderboard differs.
+fn with_changed_leaderboards(data: &DistanceData) -> DistanceData {
+    let mut data = data.clone();
+    let mut leaderboards = 0;
+    let mut changed = |has_replay: &mut bool| {
+        if leaderboards % 10 == 0 {
+            *has_replay = !*has_replay;
+        }
+        leaderboards += 1;
+    };
+    for level in &mut data.levels {
+        if let Some(entry) = level.sprint_entries.first_mut() {
+            changed(&mut entry.has_replay);
+        }
+        if let Some(entry) = level.challenge_entries.first_mut() {
+            changed(&mut entry.has_replay);
+        }
+        if let Some(entry) = level.stunt_entries.first_mut() {
+            changed(&mut entry.has_replay);
+        }
+    }
+
+    data
+}
+
+/// Creates an empty database for the benchmarks from `create_db.sql`,
+/// replacing the one a previous run left behind.
+async fn create_database(url: &str) -> Client {
+    let mut config: Config = url.parse().expect("invalid TEST_DATABASE_URL");
+    let admin = connect(&config).await;
+    for statement in [
+        format!("DROP DATABASE IF EXISTS {DATABASE_NAME} WITH (FORCE)"),
+        format!("CREATE DATABASE {DATABASE_NAME}"),
+    ] {
+        admin.batch_execute(&statement).await.unwrap();
+    }
+
+    config.dbname(DATABASE_NAME);
+    let db = connect(&config).await;
+    db.batch_execute(schema()).await.unwrap();
+
+    db
+}
+
+criterion_group!(benches, storing);
+criterion_main!(benches);
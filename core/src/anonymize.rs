@@ -0,0 +1,145 @@
+//! Pseudonymizing players in public dumps.
+
+use anyhow::{Context, Error, format_err};
+use clap::ValueEnum;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::path::Path;
+use std::{fmt, fs};
+
+/// The shortest pseudonym key accepted, in bytes.
+pub const MIN_KEY_LEN: usize = 16;
+
+/// What to do with persona names.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NamePolicy {
+    /// Keep every name
+    Keep,
+    /// Blank the names of everyone but opted-in authors
+    Redact,
+    /// Leave names out of the dump
+    Drop,
+}
+
+/// What to do with the authors of workshop levels.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthorPolicy {
+    /// Replace author IDs with the same pseudonyms as their leaderboard
+    /// entries
+    Pseudonymize,
+    /// Remove the author IDs of everyone but opted-in authors
+    Redact,
+}
+
+/// Maps Steam IDs to pseudonyms, and applies the name and author policies.
+///
+/// A pseudonym is derived from the Steam ID with HMAC-SHA256 under a secret
+/// key, so it's the same in every table and every dump made with the same
+/// key, but can't be traced back to the player without the key. Pseudonyms
+/// are negative, so they never collide with real Steam IDs. Authors who have
+/// opted in keep their real Steam ID and name everywhere, which keeps their
+/// levels linkable to them.
+pub struct Anonymizer {
+    mac: Hmac<Sha256>,
+    pub names: NamePolicy,
+    pub authors: AuthorPolicy,
+    opted_in_authors: HashSet<u64>,
+}
+
+impl fmt::Debug for Anonymizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Anonymizer")
+            .field("names", &self.names)
+            .field("authors", &self.authors)
+            .field("opted_in_authors", &self.opted_in_authors.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Anonymizer {
+    /// Returns an anonymizer keyed with `key`, which must be at least
+    /// [`MIN_KEY_LEN`] bytes long.
+    pub fn new(
+        key: &[u8],
+        names: NamePolicy,
+        authors: AuthorPolicy,
+        opted_in_authors: HashSet<u64>,
+    ) -> Result<Self, Error> {
+        if key.len() < MIN_KEY_LEN {
+            return Err(format_err!(
+                "the pseudonym key must be at least {MIN_KEY_LEN} bytes long"
+            ));
+        }
+
+        Ok(Anonymizer {
+            mac: Hmac::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            names,
+            authors,
+            opted_in_authors,
+        })
+    }
+
+    /// The number of authors who have opted in to being linkable.
+    pub fn opted_in_authors(&self) -> usize {
+        self.opted_in_authors.len()
+    }
+
+    /// Returns the pseudonym of the player `steam_id`, or their Steam ID if
+    /// they're an opted-in author.
+    pub fn player(&self, steam_id: u64) -> i64 {
+        if self.opted_in_authors.contains(&steam_id) {
+            return steam_id as i64;
+        }
+
+        let mut mac = self.mac.clone();
+        mac.update(&steam_id.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+        let bytes = hash[..8]
+            .try_into()
+            .expect("SHA-256 hashes are 32 bytes long");
+
+        i64::from_be_bytes(bytes) | i64::MIN
+    }
+
+    /// Returns what to show as the author of a level by `steam_id`, if
+    /// anything.
+    pub fn author(&self, steam_id: u64) -> Option<i64> {
+        match self.authors {
+            _ if self.opted_in_authors.contains(&steam_id) => Some(steam_id as i64),
+            AuthorPolicy::Pseudonymize => Some(self.player(steam_id)),
+            AuthorPolicy::Redact => None,
+        }
+    }
+
+    /// Returns what to show as the name of `steam_id`, if names are kept at
+    /// all.
+    pub fn name(&self, steam_id: u64, name: String) -> Option<String> {
+        match self.names {
+            NamePolicy::Drop => None,
+            _ if self.opted_in_authors.contains(&steam_id) => Some(name),
+            NamePolicy::Keep => Some(name),
+            NamePolicy::Redact => Some(String::new()),
+        }
+    }
+}
+
+/// Reads a list of Steam IDs from `path`, one per line. Blank lines and lines
+/// starting with `#` are ignored.
+pub fn read_steam_ids(path: &Path) -> Result<HashSet<u64>, Error> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("error reading {}", path.display()))?;
+
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse()
+                .with_context(|| format!("invalid Steam ID {line:?} in {}", path.display()))
+        })
+        .collect()
+}
@@ -0,0 +1,134 @@
+//! The data model: everything a run collects, in the shape it's stored in.
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use serde_with::{DisplayFromStr, serde_as};
+
+/// Everything a run collects.
+#[derive(Debug, Clone, Default)]
+pub struct DistanceData {
+    /// Official levels, followed by workshop levels.
+    pub levels: Vec<Level>,
+    /// Every player and level author, with their names.
+    pub users: Vec<User>,
+}
+
+impl DistanceData {
+    /// Returns an empty dataset.
+    pub fn new() -> Self {
+        DistanceData::default()
+    }
+}
+
+/// Which parts of the data a run should refresh, and how.
+#[derive(Debug, Clone, Copy)]
+pub struct RunOptions {
+    /// Refresh the sprint leaderboards.
+    pub sprint: bool,
+    /// Refresh the challenge leaderboards.
+    pub challenge: bool,
+    /// Refresh the stunt leaderboards.
+    pub stunt: bool,
+    /// Rewrite leaderboards even if their stored hash matches.
+    pub force_rebuild: bool,
+    /// Reuse the workshop levels stored in the database instead of querying
+    /// the Steam Workshop.
+    pub skip_workshop_query: bool,
+    /// Only resolve the names of users that aren't in the database yet.
+    pub only_new_names: bool,
+    /// Download the leaderboards of all modes at once, instead of one mode
+    /// after another.
+    pub parallel_modes: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            sprint: true,
+            challenge: true,
+            stunt: true,
+            force_rebuild: false,
+            skip_workshop_query: false,
+            only_new_names: false,
+            parallel_modes: false,
+        }
+    }
+}
+
+/// An official or workshop level, with its leaderboards.
+#[derive(Debug, Clone, Default)]
+pub struct Level {
+    /// The workshop file ID, or a negative ID for official levels.
+    pub id: i64,
+    pub name: String,
+    /// Whether the level has a leaderboard in each mode.
+    pub is_sprint: bool,
+    pub is_challenge: bool,
+    pub is_stunt: bool,
+    /// For workshop levels, the file details the Steam Web API lists, both
+    /// parsed and as returned.
+    pub workshop_level_details: Option<(PublishedFileDetailsSubset, JsonValue)>,
+    /// The entries of each mode's leaderboard, best first.
+    pub sprint_entries: Vec<TimeLeaderboardEntry>,
+    pub challenge_entries: Vec<TimeLeaderboardEntry>,
+    pub stunt_entries: Vec<ScoreLeaderboardEntry>,
+    /// Whether the leaderboard of each mode couldn't be downloaded, in which
+    /// case the stored entries are kept.
+    pub sprint_skipped: bool,
+    pub challenge_skipped: bool,
+    pub stunt_skipped: bool,
+}
+
+/// The fields of a workshop file's details the populator uses.
+#[serde_as]
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublishedFileDetailsSubset {
+    #[serde_as(as = "DisplayFromStr")]
+    #[serde(rename = "publishedfileid")]
+    pub published_file_id: i64,
+
+    /// The author's Steam ID.
+    #[serde_as(as = "DisplayFromStr")]
+    pub creator: u64,
+
+    /// The level file's name, which leaderboard names are derived from.
+    pub filename: String,
+
+    #[serde_as(as = "DisplayFromStr")]
+    pub file_size: i64,
+    pub title: String,
+    pub tags: Vec<Tag>,
+}
+
+/// A workshop tag, e.g. a game mode or difficulty.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tag {
+    pub tag: String,
+}
+
+/// A ranked entry of a sprint or challenge leaderboard.
+#[derive(Debug, Copy, Clone)]
+pub struct TimeLeaderboardEntry {
+    pub steam_id: u64,
+    /// The finish time, in milliseconds.
+    pub time: i32,
+    pub rank: u32,
+    pub has_replay: bool,
+}
+
+/// A ranked entry of a stunt leaderboard.
+#[derive(Debug, Copy, Clone)]
+pub struct ScoreLeaderboardEntry {
+    pub steam_id: u64,
+    pub score: i32,
+    pub rank: u32,
+    pub has_replay: bool,
+}
+
+/// A player or level author.
+#[derive(Debug, Clone)]
+pub struct User {
+    pub steam_id: u64,
+    /// The persona name, or empty if it couldn't be resolved.
+    pub name: String,
+}
@@ -0,0 +1,468 @@
+//! Downloading everything a run stores from Steam and the
+//! DistanceSteamDataServers.
+
+use crate::common::{
+    DistanceData, Level, PublishedFileDetailsSubset, RunOptions, ScoreLeaderboardEntry,
+    TimeLeaderboardEntry, User,
+};
+use crate::failure::FailureKind;
+use crate::leaderboard_source::{LeaderboardEntry, LeaderboardSource};
+use crate::logging;
+use crate::ranking;
+use crate::report::RunReport;
+use crate::upstream::Upstream;
+use anyhow::{Context, Error};
+use az::Az;
+use distance_util::LeaderboardGameMode;
+use futures::stream::{self};
+use futures::{StreamExt, future};
+use itertools::Itertools;
+use serde_json::Value as JsonValue;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::pin::pin;
+use std::time::Instant;
+use tap::{Pipe, TapFallible};
+use tracing::field::Empty;
+use tracing::{Instrument, debug_span, info, info_span, warn};
+
+/// Collects the levels, leaderboards and players to store from `upstream`.
+///
+/// Leaderboards that fail to download are marked as skipped rather than
+/// failing the run. `db` is only read, to reuse stored workshop levels or
+/// names as `options` asks.
+pub async fn run(
+    upstream: &impl Upstream,
+    db: &tokio_postgres::Client,
+    options: RunOptions,
+    report: &mut RunReport,
+) -> Result<DistanceData, Error> {
+    let mut data = DistanceData::new();
+
+    data.levels.extend(official_levels());
+
+    let workshop_span = info_span!(
+        "workshop_levels",
+        from_db = options.skip_workshop_query,
+        levels = Empty
+    );
+    let phase = report.start_phase("workshop_query");
+    let all_workshop_json = if options.skip_workshop_query {
+        load_stored_workshop_json(db)
+            .instrument(workshop_span.clone())
+            .await?
+    } else {
+        query_all_workshop_json(upstream)
+            .instrument(workshop_span.clone())
+            .await
+            .context("error querying the Steam Workshop")
+            .context(FailureKind::Upstream)?
+    };
+    let filtered_workshop_data = all_workshop_json.into_iter().filter_map(|json| {
+        let details: PublishedFileDetailsSubset = serde_json::from_value(json.clone()).ok()?;
+        Some((details, json))
+    });
+    let workshop_levels = filtered_workshop_data.filter_map(|(details, json)| {
+        let is_sprint = details.tags.iter().any(|x| x.tag == "Sprint");
+        let is_challenge = details.tags.iter().any(|x| x.tag == "Challenge");
+        let is_stunt = details.tags.iter().any(|x| x.tag == "Stunt");
+
+        if !details.filename.is_empty() && details.file_size > 0 {
+            Some(Level {
+                id: details.published_file_id,
+                name: details.title.clone(),
+                is_sprint,
+                is_challenge,
+                is_stunt,
+                workshop_level_details: Some((details, json)),
+                ..Level::default()
+            })
+        } else {
+            None
+        }
+    });
+
+    data.levels.extend(workshop_levels);
+    phase.finish(report, data.levels.len());
+    workshop_span.record("levels", data.levels.len());
+    drop(workshop_span);
+
+    // Download the leaderboards of each mode, either one mode after another
+    // or all at once, sharing the upstream's request budget
+    let fetch_mode = |name: &'static str,
+                      game_mode: LeaderboardGameMode,
+                      game_mode_predicate: fn(&Level) -> bool,
+                      enabled: bool| {
+        let levels = &data.levels;
+        async move {
+            if !enabled {
+                return None;
+            }
+
+            let span = info_span!("mode_entries", mode = name, levels = Empty, entries = Empty);
+            let start = Instant::now();
+            let entries = get_mode_entries(
+                upstream,
+                upstream.concurrency(),
+                levels,
+                game_mode,
+                game_mode_predicate,
+            )
+            .instrument(span.clone())
+            .await;
+            span.record("levels", entries.len());
+
+            Some((span, start.elapsed(), entries))
+        }
+    };
+    let sprint = fetch_mode(
+        "sprint",
+        LeaderboardGameMode::Sprint,
+        |l| l.is_sprint,
+        options.sprint,
+    );
+    let challenge = fetch_mode(
+        "challenge",
+        LeaderboardGameMode::Challenge,
+        |l| l.is_challenge,
+        options.challenge,
+    );
+    let stunt = fetch_mode(
+        "stunt",
+        LeaderboardGameMode::Stunt,
+        |l| l.is_stunt,
+        options.stunt,
+    );
+    let (sprint, challenge, stunt) = if options.parallel_modes {
+        future::join3(sprint, challenge, stunt).await
+    } else {
+        (sprint.await, challenge.await, stunt.await)
+    };
+
+    if let Some((span, duration, entries)) = sprint {
+        for level in data.levels.iter_mut().filter(|l| l.is_sprint) {
+            level.sprint_skipped = true;
+        }
+        for (i, level_entries_raw) in entries {
+            data.levels[i].sprint_skipped = false;
+            let level_entries =
+                level_entries_raw
+                    .into_iter()
+                    .map(|(entry, rank)| TimeLeaderboardEntry {
+                        steam_id: entry.steam_id,
+                        time: entry.score,
+                        rank,
+                        has_replay: entry.has_replay,
+                    });
+
+            data.levels[i].sprint_entries.extend(level_entries);
+        }
+
+        let entry_count = data.levels.iter().map(|l| l.sprint_entries.len()).sum();
+        report.record("sprint_entries", duration, entry_count);
+        span.record("entries", entry_count);
+
+        let skipped = data.levels.iter().filter(|l| l.sprint_skipped).count();
+        report.record_skipped_leaderboards("sprint", skipped);
+        if skipped > 0 {
+            warn!(
+                mode = "sprint",
+                skipped, "Skipped leaderboards that couldn't be downloaded"
+            );
+        }
+    }
+
+    if let Some((span, duration, entries)) = challenge {
+        for level in data.levels.iter_mut().filter(|l| l.is_challenge) {
+            level.challenge_skipped = true;
+        }
+        for (i, level_entries_raw) in entries {
+            data.levels[i].challenge_skipped = false;
+            let level_entries =
+                level_entries_raw
+                    .into_iter()
+                    .map(|(entry, rank)| TimeLeaderboardEntry {
+                        steam_id: entry.steam_id,
+                        time: entry.score,
+                        rank,
+                        has_replay: entry.has_replay,
+                    });
+
+            data.levels[i].challenge_entries.extend(level_entries);
+        }
+
+        let entry_count = data.levels.iter().map(|l| l.challenge_entries.len()).sum();
+        report.record("challenge_entries", duration, entry_count);
+        span.record("entries", entry_count);
+
+        let skipped = data.levels.iter().filter(|l| l.challenge_skipped).count();
+        report.record_skipped_leaderboards("challenge", skipped);
+        if skipped > 0 {
+            warn!(
+                mode = "challenge",
+                skipped, "Skipped leaderboards that couldn't be downloaded"
+            );
+        }
+    }
+
+    if let Some((span, duration, entries)) = stunt {
+        for level in data.levels.iter_mut().filter(|l| l.is_stunt) {
+            level.stunt_skipped = true;
+        }
+        for (i, level_entries_raw) in entries {
+            data.levels[i].stunt_skipped = false;
+            let level_entries =
+                level_entries_raw
+                    .into_iter()
+                    .map(|(entry, rank)| ScoreLeaderboardEntry {
+                        steam_id: entry.steam_id,
+                        score: entry.score,
+                        rank,
+                        has_replay: entry.has_replay,
+                    });
+
+            data.levels[i].stunt_entries.extend(level_entries);
+        }
+
+        let entry_count = data.levels.iter().map(|l| l.stunt_entries.len()).sum();
+        report.record("stunt_entries", duration, entry_count);
+        span.record("entries", entry_count);
+
+        let skipped = data.levels.iter().filter(|l| l.stunt_skipped).count();
+        report.record_skipped_leaderboards("stunt", skipped);
+        if skipped > 0 {
+            warn!(
+                mode = "stunt",
+                skipped, "Skipped leaderboards that couldn't be downloaded"
+            );
+        }
+    }
+
+    // Resolve Player and Author names
+    {
+        let mut user_ids = HashSet::new();
+
+        // Level authors
+        data.levels
+            .iter()
+            .filter_map(|level| {
+                level
+                    .workshop_level_details
+                    .as_ref()
+                    .map(|(details, _json)| details.creator)
+            })
+            .for_each(|steam_id| {
+                user_ids.insert(steam_id);
+            });
+
+        // Sprint players
+        data.levels
+            .iter()
+            .flat_map(|level| level.sprint_entries.iter().map(|entry| entry.steam_id))
+            .for_each(|steam_id| {
+                user_ids.insert(steam_id);
+            });
+
+        // Challenge players
+        data.levels
+            .iter()
+            .flat_map(|level| level.challenge_entries.iter().map(|entry| entry.steam_id))
+            .for_each(|steam_id| {
+                user_ids.insert(steam_id);
+            });
+
+        // Stunt players
+        data.levels
+            .iter()
+            .flat_map(|level| level.stunt_entries.iter().map(|entry| entry.steam_id))
+            .for_each(|steam_id| {
+                user_ids.insert(steam_id);
+            });
+
+        if options.only_new_names {
+            let rows = db.query("SELECT steam_id FROM users", &[]).await?;
+            for row in rows {
+                user_ids.remove(&(row.get::<_, i64>(0) as u64));
+            }
+        }
+
+        let user_ids = user_ids.into_iter().collect_vec();
+
+        let span = info_span!("persona_names", users = user_ids.len());
+        let phase = report.start_phase("persona_names");
+        let mut user_names = Vec::with_capacity(user_ids.len());
+        for (i, chunk) in user_ids.chunks(1000).enumerate() {
+            let request_span = debug_span!(
+                parent: &span,
+                "persona_names_request",
+                request = i,
+                users = chunk.len()
+            );
+            let names = upstream
+                .persona_names(chunk.to_vec())
+                .instrument(request_span)
+                .await
+                .context("error resolving persona names")
+                .context(FailureKind::Upstream)?;
+            user_names.extend(names);
+        }
+        phase.finish(report, user_names.len());
+        drop(span);
+
+        let users = user_ids
+            .iter()
+            .zip(user_names)
+            .map(|(&steam_id, name)| User {
+                steam_id,
+                name: name.unwrap_or_default(),
+            })
+            .collect();
+
+        data.users = users;
+    }
+
+    Ok(data)
+}
+
+/// Returns the official levels, each marked with the game modes it's played
+/// in, in order of ID.
+pub fn official_levels() -> Vec<Level> {
+    let mut official_levels: HashMap<&'static str, Level> = HashMap::new();
+    for (game_mode, idx_offset) in &[
+        (LeaderboardGameMode::Sprint, -1000),
+        (LeaderboardGameMode::Challenge, -2000),
+        (LeaderboardGameMode::Stunt, -3000),
+    ] {
+        for (idx, &level_name) in game_mode.official_level_names().iter().enumerate() {
+            let entry = official_levels.entry(level_name).or_insert(Level {
+                id: idx_offset - idx.az::<i64>(),
+                name: level_name.to_owned(),
+                is_sprint: false,
+                is_challenge: false,
+                is_stunt: false,
+                ..Level::default()
+            });
+
+            match game_mode {
+                LeaderboardGameMode::Sprint => entry.is_sprint = true,
+                LeaderboardGameMode::Challenge => entry.is_challenge = true,
+                LeaderboardGameMode::Stunt => entry.is_stunt = true,
+            }
+        }
+    }
+
+    official_levels
+        .into_values()
+        .sorted_by_key(|level| Reverse(level.id))
+        .collect()
+}
+
+async fn query_all_workshop_json(upstream: &impl Upstream) -> Result<Vec<JsonValue>, Error> {
+    info!("Querying all workshop levels");
+    let pb = logging::spinner();
+    pb.set_message("Querying all workshop levels");
+
+    let mut pages = pin!(upstream.workshop_pages());
+    let mut all_workshop_json = Vec::new();
+    for page_number in 0.. {
+        let span = debug_span!("workshop_page", page = page_number, files = Empty);
+        let Some(page) = pages.next().instrument(span.clone()).await else {
+            break;
+        };
+        let page = page?;
+        span.record("files", page.len());
+        all_workshop_json.extend(page);
+        pb.tick();
+    }
+    pb.finish();
+
+    Ok(all_workshop_json)
+}
+
+/// Returns the workshop level details stored by a previous run, so the
+/// leaderboards can be refreshed without querying the whole workshop.
+async fn load_stored_workshop_json(db: &tokio_postgres::Client) -> Result<Vec<JsonValue>, Error> {
+    info!("Loading workshop levels from the database");
+    let rows = db
+        .query("SELECT raw_details FROM workshop_level_details", &[])
+        .await?;
+
+    Ok(rows.into_iter().map(|row| row.get(0)).collect())
+}
+
+/// Returns the leaderboard entries for the specified `game_mode`.
+///
+/// The return value is a vec of tuples, where each tuple consists of 1. an
+/// index into the passed-in `levels` slice, and 2. a vec containing all
+/// entries for that particular level, together with the rank for each entry.
+async fn get_mode_entries(
+    source: &impl LeaderboardSource,
+    concurrency: usize,
+    levels: &[Level],
+    game_mode: LeaderboardGameMode,
+    game_mode_predicate: impl Fn(&Level) -> bool,
+) -> Vec<(usize, Vec<(LeaderboardEntry, u32)>)> {
+    let mode_level_leaderboard_names: Vec<_> = levels
+        .iter()
+        .enumerate()
+        .filter_map(|(i, level)| {
+            if !game_mode_predicate(level) {
+                return None;
+            }
+
+            let leaderboard_name_string =
+                if let Some((details, _json)) = &level.workshop_level_details {
+                    // Workshop level
+                    let filename_without_extension = &details.filename
+                        [0..(details.filename.len().saturating_sub(".bytes".len()))];
+                    distance_util::create_leaderboard_name_string(
+                        filename_without_extension,
+                        game_mode,
+                        Some(details.creator),
+                    )
+                } else {
+                    // Official level
+                    distance_util::create_leaderboard_name_string(&level.name, game_mode, None)
+                };
+
+            leaderboard_name_string.ok().map(|s| (i, s))
+        })
+        .collect();
+
+    let pb = logging::progress_bar(mode_level_leaderboard_names.len() as u64);
+    let entries: Vec<(usize, Vec<(LeaderboardEntry, u32)>)> = mode_level_leaderboard_names
+        .into_iter()
+        .map(|(i, leaderboard_name_string)| {
+            let span = debug_span!(
+                "level_entries",
+                level_id = levels[i].id,
+                leaderboard = %leaderboard_name_string,
+                entries = Empty
+            );
+            let level_span = span.clone();
+            async move {
+                let level_entries = source
+                    .leaderboard_entries_all(&leaderboard_name_string)
+                    .instrument(debug_span!("leaderboard_entries_all"))
+                    .await
+                    .tap_err(|err| {
+                        warn!(leaderboard = %leaderboard_name_string, "failed to download entries: {err}")
+                    })
+                    .ok()?;
+                level_span.record("entries", level_entries.len());
+
+                Some((i, ranking::rank(level_entries)))
+            }
+            .instrument(span)
+        })
+        .pipe(stream::iter)
+        .buffer_unordered(concurrency)
+        .inspect(|_| pb.inc(1))
+        .filter_map(future::ready)
+        .collect()
+        .await;
+
+    pb.finish_and_clear();
+
+    entries
+}
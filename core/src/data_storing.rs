@@ -0,0 +1,171 @@
+//! Writing collected data to the database.
+
+use crate::common::{DistanceData, RunOptions, ScoreLeaderboardEntry, TimeLeaderboardEntry};
+use crate::opt_out;
+use crate::postgres_storage::PostgresStorage;
+use crate::report::RunReport;
+use crate::storage::{LeaderboardEntries, LeaderboardReplacement, Storage};
+use anyhow::{Context, Error};
+use fxhash::FxHasher;
+use std::hash::{Hash, Hasher};
+use tracing::{Instrument, info, info_span};
+
+fn compute_sprint_hash(entries: &[TimeLeaderboardEntry]) -> i64 {
+    let mut hasher = FxHasher::default();
+    for entry in entries {
+        entry.steam_id.hash(&mut hasher);
+        entry.time.hash(&mut hasher);
+        entry.has_replay.hash(&mut hasher);
+    }
+    hasher.finish() as i64
+}
+
+fn compute_challenge_hash(entries: &[TimeLeaderboardEntry]) -> i64 {
+    let mut hasher = FxHasher::default();
+    for entry in entries {
+        entry.steam_id.hash(&mut hasher);
+        entry.time.hash(&mut hasher);
+        entry.has_replay.hash(&mut hasher);
+    }
+    hasher.finish() as i64
+}
+
+fn compute_stunt_hash(entries: &[ScoreLeaderboardEntry]) -> i64 {
+    let mut hasher = FxHasher::default();
+    for entry in entries {
+        entry.steam_id.hash(&mut hasher);
+        entry.score.hash(&mut hasher);
+        entry.has_replay.hash(&mut hasher);
+    }
+    hasher.finish() as i64
+}
+
+/// Stores `data` in the Postgres database `db`, in a single transaction.
+///
+/// Leaderboards whose hash matches the stored one are left alone unless
+/// `options` forces a rebuild, as are skipped leaderboards and those of modes
+/// `options` leaves out. Levels and users that are no longer listed are kept.
+/// Players on the [opt-out list](opt_out) are stored with a hidden name, and
+/// their names are hidden in `data` as well, so copies and exports made from
+/// it afterwards don't have them either.
+pub async fn run(
+    db: &mut tokio_postgres::Client,
+    data: &mut DistanceData,
+    options: RunOptions,
+    report: &mut RunReport,
+) -> Result<(), Error> {
+    let name_opt_outs = opt_out::list(db)
+        .await
+        .context("error reading the name opt-out list")?;
+    opt_out::hide_names(&mut data.users, &name_opt_outs);
+
+    let storage = PostgresStorage::begin(db).await?;
+    store(storage, data, options, report).await
+}
+
+/// Stores `data` in `storage`, like [`run`] does in Postgres, and commits it.
+/// Names are stored as they are in `data`, so it should come from [`run`] to
+/// have the opted-out ones hidden.
+pub async fn store(
+    mut storage: impl Storage,
+    data: &DistanceData,
+    options: RunOptions,
+    report: &mut RunReport,
+) -> Result<(), Error> {
+    let phase = report.start_phase("store_users");
+    async {
+        info!("Updating users in the database");
+        storage.upsert_users(&data.users).await
+    }
+    .instrument(info_span!("store_users", users = data.users.len()))
+    .await?;
+    phase.finish(report, data.users.len());
+
+    let phase = report.start_phase("store_levels");
+    async {
+        info!("Updating levels in the database");
+        storage.upsert_levels(&data.levels).await
+    }
+    .instrument(info_span!("store_levels", levels = data.levels.len()))
+    .await?;
+    phase.finish(report, data.levels.len());
+
+    let phase = report.start_phase("store_details");
+    let workshop_levels = data
+        .levels
+        .iter()
+        .filter(|level| level.workshop_level_details.is_some())
+        .count();
+    async {
+        info!("Updating workshop level details");
+        storage.upsert_workshop_details(&data.levels).await
+    }
+    .instrument(info_span!("store_details", levels = workshop_levels))
+    .await?;
+    phase.finish(report, workshop_levels);
+
+    let phase = report.start_phase("store_entries");
+    let entry_count: usize = data
+        .levels
+        .iter()
+        .map(|l| l.sprint_entries.len() + l.challenge_entries.len() + l.stunt_entries.len())
+        .sum();
+    async {
+        info!("Updating leaderboard entries");
+        let level_ids: Vec<_> = data.levels.iter().map(|level| level.id).collect();
+        let stored_hashes = storage.leaderboard_hashes(&level_ids).await?;
+
+        // Only rewrite the leaderboards whose hash differs
+        let mut replacements = Vec::new();
+        for (level, stored) in data.levels.iter().zip(stored_hashes) {
+            let mut replace = |entries, hash, stored_hash: Option<i64>| {
+                if options.force_rebuild || stored_hash != Some(hash) {
+                    replacements.push(LeaderboardReplacement {
+                        level_id: level.id,
+                        entries,
+                        hash,
+                    });
+                }
+            };
+
+            if options.sprint && level.is_sprint && !level.sprint_skipped {
+                replace(
+                    LeaderboardEntries::Sprint(&level.sprint_entries),
+                    compute_sprint_hash(&level.sprint_entries),
+                    stored.sprint,
+                );
+            }
+            if options.challenge && level.is_challenge && !level.challenge_skipped {
+                replace(
+                    LeaderboardEntries::Challenge(&level.challenge_entries),
+                    compute_challenge_hash(&level.challenge_entries),
+                    stored.challenge,
+                );
+            }
+            if options.stunt && level.is_stunt && !level.stunt_skipped {
+                replace(
+                    LeaderboardEntries::Stunt(&level.stunt_entries),
+                    compute_stunt_hash(&level.stunt_entries),
+                    stored.stunt,
+                );
+            }
+        }
+
+        storage.replace_leaderboards(&replacements).await
+    }
+    .instrument(info_span!(
+        "store_entries",
+        levels = data.levels.len(),
+        entries = entry_count
+    ))
+    .await?;
+    phase.finish(report, entry_count);
+
+    info!("Updating 'last_updated' timestamp");
+    storage.mark_updated().await?;
+
+    info!("Committing changes");
+    storage.commit().await?;
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+//! Connecting to the database, and reading back what's stored.
+
+use crate::common::{
+    DistanceData, Level, PublishedFileDetailsSubset, ScoreLeaderboardEntry, TimeLeaderboardEntry,
+    User,
+};
+use crate::failure::FailureKind;
+use crate::lock;
+use anyhow::{Context, Error};
+use futures::FutureExt;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::env;
+use tokio_postgres::IsolationLevel;
+use tracing::{error, info};
+
+/// Connects to the database `DATABASE_URL` points to.
+pub async fn establish_connection() -> Result<tokio_postgres::Client, Error> {
+    let database_url = env::var("DATABASE_URL")
+        .context("Environment variable DATABASE_URL is not set")
+        .context(FailureKind::Config)?;
+
+    connect(&database_url).await
+}
+
+/// Connects to the database at `database_url`.
+pub async fn connect(database_url: &str) -> Result<tokio_postgres::Client, Error> {
+    let mut config: tokio_postgres::Config = database_url
+        .parse()
+        .context("Invalid DATABASE_URL")
+        .context(FailureKind::Config)?;
+    if config.get_application_name().is_none() {
+        config.application_name(lock::application_name());
+    }
+
+    let (client, connection) = config.connect(tokio_postgres::NoTls).await?;
+
+    let connection = connection.map(|r| {
+        if let Err(e) = r {
+            error!("connection error: {e}");
+        }
+    });
+    tokio::spawn(connection);
+
+    Ok(client)
+}
+
+/// Reads everything stored in the database, from a single snapshot.
+///
+/// Official levels come first, in the order of
+/// [`official_levels`](crate::data_collection::official_levels), followed by
+/// workshop levels in order of ID. Leaderboard entries are in order of rank.
+pub async fn load(db: &mut tokio_postgres::Client) -> Result<DistanceData, Error> {
+    info!("Loading the stored data");
+    let transaction = db
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .await?;
+
+    let users = transaction
+        .query("SELECT steam_id, name FROM users ORDER BY steam_id", &[])
+        .await?
+        .into_iter()
+        .map(|row| User {
+            steam_id: row.get::<_, i64>(0) as u64,
+            name: row.get(1),
+        })
+        .collect();
+
+    let mut sprint_entries = HashMap::<i64, Vec<_>>::new();
+    for row in transaction
+        .query(
+            "SELECT level_id, steam_id, time, rank, has_replay FROM sprint_leaderboard_entries ORDER BY level_id, rank, steam_id",
+            &[],
+        )
+        .await?
+    {
+        sprint_entries
+            .entry(row.get(0))
+            .or_default()
+            .push(TimeLeaderboardEntry {
+                steam_id: row.get::<_, i64>(1) as u64,
+                time: row.get(2),
+                rank: row.get::<_, i32>(3) as u32,
+                has_replay: row.get(4),
+            });
+    }
+
+    let mut challenge_entries = HashMap::<i64, Vec<_>>::new();
+    for row in transaction
+        .query(
+            "SELECT level_id, steam_id, time, rank, has_replay FROM challenge_leaderboard_entries ORDER BY level_id, rank, steam_id",
+            &[],
+        )
+        .await?
+    {
+        challenge_entries
+            .entry(row.get(0))
+            .or_default()
+            .push(TimeLeaderboardEntry {
+                steam_id: row.get::<_, i64>(1) as u64,
+                time: row.get(2),
+                rank: row.get::<_, i32>(3) as u32,
+                has_replay: row.get(4),
+            });
+    }
+
+    let mut stunt_entries = HashMap::<i64, Vec<_>>::new();
+    for row in transaction
+        .query(
+            "SELECT level_id, steam_id, score, rank, has_replay FROM stunt_leaderboard_entries ORDER BY level_id, rank, steam_id",
+            &[],
+        )
+        .await?
+    {
+        stunt_entries
+            .entry(row.get(0))
+            .or_default()
+            .push(ScoreLeaderboardEntry {
+                steam_id: row.get::<_, i64>(1) as u64,
+                score: row.get(2),
+                rank: row.get::<_, i32>(3) as u32,
+                has_replay: row.get(4),
+            });
+    }
+
+    let mut levels = Vec::new();
+    for row in transaction
+        .query(
+            "SELECT id, name, is_sprint, is_challenge, is_stunt, raw_details FROM levels LEFT JOIN workshop_level_details ON level_id = id",
+            &[],
+        )
+        .await?
+    {
+        let id = row.get(0);
+        let workshop_level_details = row
+            .get::<_, Option<JsonValue>>(5)
+            .map(|json| {
+                let details: PublishedFileDetailsSubset = serde_json::from_value(json.clone())
+                    .with_context(|| format!("invalid stored details of workshop level {id}"))?;
+
+                Ok::<_, Error>((details, json))
+            })
+            .transpose()?;
+
+        levels.push(Level {
+            id,
+            name: row.get(1),
+            is_sprint: row.get(2),
+            is_challenge: row.get(3),
+            is_stunt: row.get(4),
+            workshop_level_details,
+            sprint_entries: sprint_entries.remove(&id).unwrap_or_default(),
+            challenge_entries: challenge_entries.remove(&id).unwrap_or_default(),
+            stunt_entries: stunt_entries.remove(&id).unwrap_or_default(),
+            ..Level::default()
+        });
+    }
+    // Official levels have negative IDs, counting down from the first one
+    levels.sort_by_key(|level| (level.workshop_level_details.is_some(), level.id.abs()));
+
+    transaction.commit().await?;
+
+    Ok(DistanceData { levels, users })
+}
@@ -0,0 +1,378 @@
+//! Dumping each table of the database as CSV and JSON Lines, for public
+//! dumps that can be loaded without Postgres.
+//!
+//! A dump directory holds `<table>.csv` and `<table>.jsonl` for each table,
+//! and a `manifest.json` listing when the data was last updated, and the row
+//! count and SHA-256 checksum of every file. The manifest is written last, so
+//! a dump without one is incomplete.
+//!
+//! Dumps can be anonymized with an [`Anonymizer`], which pseudonymizes
+//! players and applies its policies to names and level authors.
+
+use crate::anonymize::{Anonymizer, AuthorPolicy, NamePolicy};
+use anyhow::{Context, Error};
+use futures::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use tokio_postgres::{Client, IsolationLevel};
+use tracing::{Instrument, info, info_span};
+
+/// The version of the dump's layout, bumped whenever a column is changed or
+/// removed.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A table to dump: its name, its columns, and the order of its rows.
+#[derive(Debug)]
+struct Table {
+    name: &'static str,
+    columns: &'static [&'static str],
+    order_by: &'static str,
+    players: Players,
+}
+
+/// Which players a table's rows refer to, to be anonymized.
+#[derive(Debug, Copy, Clone)]
+enum Players {
+    None,
+    /// Players by `steam_id`, with their `name`
+    Users,
+    /// Level authors by `author_steam_id`, and as the `creator` of
+    /// `raw_details`
+    Authors,
+    /// Players by `steam_id`
+    Entries,
+}
+
+impl Table {
+    /// The columns dumped, which leave out names if `anonymizer` drops them.
+    fn columns(&self, anonymizer: Option<&Anonymizer>) -> Vec<&'static str> {
+        let drops_names = matches!(self.players, Players::Users)
+            && anonymizer.is_some_and(|a| a.names == NamePolicy::Drop);
+
+        self.columns
+            .iter()
+            .copied()
+            .filter(|&column| !(drops_names && column == "name"))
+            .collect()
+    }
+}
+
+/// The tables that are dumped. The leaderboard hashes of `levels` are left
+/// out, since they only matter to the populator.
+const TABLES: &[Table] = &[
+    Table {
+        name: "levels",
+        columns: &["id", "name", "is_sprint", "is_challenge", "is_stunt"],
+        order_by: "id",
+        players: Players::None,
+    },
+    Table {
+        name: "users",
+        columns: &["steam_id", "name"],
+        order_by: "steam_id",
+        players: Players::Users,
+    },
+    Table {
+        name: "workshop_level_details",
+        columns: &[
+            "level_id",
+            "author_steam_id",
+            "time_created",
+            "time_updated",
+            "tags",
+            "raw_details",
+        ],
+        order_by: "level_id",
+        players: Players::Authors,
+    },
+    Table {
+        name: "sprint_leaderboard_entries",
+        columns: &["level_id", "steam_id", "time", "rank", "has_replay"],
+        order_by: "level_id, rank, steam_id",
+        players: Players::Entries,
+    },
+    Table {
+        name: "challenge_leaderboard_entries",
+        columns: &["level_id", "steam_id", "time", "rank", "has_replay"],
+        order_by: "level_id, rank, steam_id",
+        players: Players::Entries,
+    },
+    Table {
+        name: "stunt_leaderboard_entries",
+        columns: &["level_id", "steam_id", "score", "rank", "has_replay"],
+        order_by: "level_id, rank, steam_id",
+        players: Players::Entries,
+    },
+];
+
+/// What a dump contains, as written to its `manifest.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Manifest {
+    pub format_version: u32,
+    /// When the database was last updated by a populator run, in UTC.
+    pub last_updated: Option<String>,
+    /// How the dump was anonymized, if it was.
+    pub anonymization: Option<AnonymizationManifest>,
+    pub tables: Vec<TableManifest>,
+}
+
+/// The policies an anonymized dump was made with.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnonymizationManifest {
+    pub names: NamePolicy,
+    pub authors: AuthorPolicy,
+    /// The number of authors who have opted in to keeping their Steam ID.
+    pub opted_in_authors: usize,
+}
+
+/// A dumped table.
+#[derive(Debug, Clone, Serialize)]
+pub struct TableManifest {
+    pub name: &'static str,
+    pub columns: Vec<&'static str>,
+    pub rows: u64,
+    /// The CSV and JSON Lines files.
+    pub files: Vec<FileManifest>,
+}
+
+/// A file of a dump.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileManifest {
+    /// The path relative to the dump directory.
+    pub path: String,
+    pub bytes: u64,
+    /// The SHA-256 checksum, in hex.
+    pub sha256: String,
+}
+
+/// Dumps every table of `db` to `dir` from a single snapshot, creating `dir`
+/// if needed and replacing any dump written there before. The dump is
+/// anonymized if an `anonymizer` is given.
+///
+/// Rows are streamed from the database to the files, so the tables don't
+/// need to fit in memory.
+pub async fn write(
+    db: &mut Client,
+    dir: &Path,
+    anonymizer: Option<&Anonymizer>,
+) -> Result<Manifest, Error> {
+    info!(dir = %dir.display(), "Dumping the database");
+    fs::create_dir_all(dir)
+        .with_context(|| format!("error creating directory {}", dir.display()))?;
+    let manifest_path = dir.join("manifest.json");
+    if let Err(e) = fs::remove_file(&manifest_path)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        return Err(Error::new(e).context("error removing the previous manifest"));
+    }
+
+    let transaction = db
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()
+        .await?;
+    transaction
+        .batch_execute("SET LOCAL TIME ZONE 'UTC'")
+        .await?;
+
+    let last_updated = transaction
+        .query_opt(
+            "SELECT to_char(last_updated, 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') FROM metadata",
+            &[],
+        )
+        .await?
+        .and_then(|row| row.get(0));
+
+    let mut tables = Vec::new();
+    for table in TABLES {
+        let manifest = dump_table(&transaction, table, dir, anonymizer)
+            .instrument(info_span!("dump_table", table = table.name))
+            .await
+            .with_context(|| format!("error dumping table {}", table.name))?;
+        tables.push(manifest);
+    }
+    transaction.commit().await?;
+
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        last_updated,
+        anonymization: anonymizer.map(|a| AnonymizationManifest {
+            names: a.names,
+            authors: a.authors,
+            opted_in_authors: a.opted_in_authors(),
+        }),
+        tables,
+    };
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|| format!("error writing {}", manifest_path.display()))?;
+
+    Ok(manifest)
+}
+
+async fn dump_table(
+    transaction: &tokio_postgres::Transaction<'_>,
+    table: &Table,
+    dir: &Path,
+    anonymizer: Option<&Anonymizer>,
+) -> Result<TableManifest, Error> {
+    let columns = table.columns(anonymizer);
+    let csv_path = format!("{}.csv", table.name);
+    let jsonl_path = format!("{}.jsonl", table.name);
+    let mut csv = csv::Writer::from_writer(HashingWriter::create(&dir.join(&csv_path))?);
+    let mut jsonl = HashingWriter::create(&dir.join(&jsonl_path))?;
+    csv.write_record(&columns)?;
+
+    // Postgres renders each row as JSON, which is written out as is and split
+    // into CSV fields
+    let sql = format!(
+        "SELECT row_to_json(t)::text FROM (SELECT {} FROM {} ORDER BY {}) t",
+        table.columns.join(", "),
+        table.name,
+        table.order_by
+    );
+    let rows = transaction.query_raw(&sql, iter::empty::<i32>()).await?;
+    futures::pin_mut!(rows);
+    let mut row_count = 0;
+    while let Some(row) = rows.try_next().await? {
+        let json: &str = row.get(0);
+        let mut value: Map<String, JsonValue> = serde_json::from_str(json)?;
+        match anonymizer {
+            Some(anonymizer) => {
+                anonymize(anonymizer, table.players, &mut value);
+                serde_json::to_writer(&mut jsonl, &value)?;
+            }
+            None => jsonl.write_all(json.as_bytes())?,
+        }
+        jsonl.write_all(b"\n")?;
+
+        csv.write_record(
+            columns
+                .iter()
+                .map(|&column| value.get(column).map(csv_field).unwrap_or_default()),
+        )?;
+        row_count += 1;
+    }
+
+    let csv = csv.into_inner().map_err(|e| e.into_error())?;
+
+    Ok(TableManifest {
+        name: table.name,
+        columns,
+        rows: row_count,
+        files: vec![csv.finish(csv_path)?, jsonl.finish(jsonl_path)?],
+    })
+}
+
+/// Replaces the players `row` refers to with their pseudonyms, and applies
+/// the name and author policies of `anonymizer`.
+fn anonymize(anonymizer: &Anonymizer, players: Players, row: &mut Map<String, JsonValue>) {
+    let steam_id =
+        |row: &Map<String, JsonValue>, column| row.get(column).and_then(JsonValue::as_u64);
+
+    match players {
+        Players::None => {}
+        Players::Users => {
+            if let Some(steam_id) = steam_id(row, "steam_id") {
+                let name = match row.shift_remove("name") {
+                    Some(JsonValue::String(name)) => name,
+                    _ => String::new(),
+                };
+                if let Some(name) = anonymizer.name(steam_id, name) {
+                    row.insert("name".to_owned(), name.into());
+                }
+                row.insert("steam_id".to_owned(), anonymizer.player(steam_id).into());
+            }
+        }
+        Players::Authors => {
+            let author = steam_id(row, "author_steam_id").and_then(|id| anonymizer.author(id));
+            row.insert("author_steam_id".to_owned(), author.into());
+            if let Some(JsonValue::Object(details)) = row.get_mut("raw_details") {
+                match author {
+                    Some(author) => {
+                        details.insert("creator".to_owned(), author.to_string().into());
+                    }
+                    None => {
+                        details.shift_remove("creator");
+                    }
+                }
+            }
+        }
+        Players::Entries => {
+            if let Some(steam_id) = steam_id(row, "steam_id") {
+                row.insert("steam_id".to_owned(), anonymizer.player(steam_id).into());
+            }
+        }
+    }
+}
+
+/// Returns `value` as a CSV field: strings as they are, nulls as empty
+/// fields, and arrays and objects as JSON.
+fn csv_field(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => String::new(),
+        JsonValue::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Writes a file through a temporary one, computing its checksum on the way.
+#[derive(Debug)]
+struct HashingWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    temp_path: PathBuf,
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl HashingWriter {
+    fn create(path: &Path) -> Result<Self, Error> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        let file = File::create(&temp_path)
+            .with_context(|| format!("error creating {}", temp_path.display()))?;
+
+        Ok(HashingWriter {
+            file: BufWriter::new(file),
+            path: path.to_owned(),
+            temp_path,
+            hasher: Sha256::new(),
+            bytes: 0,
+        })
+    }
+
+    /// Moves the file into place, and returns its entry in the manifest
+    /// under `relative_path`.
+    fn finish(mut self, relative_path: String) -> Result<FileManifest, Error> {
+        self.file.flush()?;
+        fs::rename(&self.temp_path, &self.path)
+            .with_context(|| format!("error writing {}", self.path.display()))?;
+
+        Ok(FileManifest {
+            path: relative_path,
+            bytes: self.bytes,
+            sha256: format!("{:x}", self.hasher.finalize()),
+        })
+    }
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
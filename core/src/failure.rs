@@ -0,0 +1,85 @@
+//! Why a run failed, and the exit code each kind of failure maps to.
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// The exit code of a run that failed for a reason not covered by
+/// [`FailureKind`].
+pub const EXIT_FAILURE: u8 = 1;
+
+/// The exit code of a run that stored everything it could, but had to skip
+/// some leaderboards.
+pub const EXIT_PARTIAL: u8 = 6;
+
+/// Why a run failed. Attach it to an error with `.context(FailureKind::...)`
+/// to choose the exit code the populator returns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// Missing or invalid configuration; retrying won't help.
+    Config,
+    /// Steam or the gRPC server couldn't be reached.
+    Upstream,
+    /// The collected data looked wrong, so nothing was stored.
+    SafetyGuard,
+    /// A database query failed.
+    Database,
+    /// Another populator holds the lock on the database.
+    Locked,
+}
+
+impl FailureKind {
+    /// Returns the kind attached to `error`. Errors without one count as
+    /// database errors if they were caused by a Postgres error.
+    pub fn of(error: &Error) -> Option<Self> {
+        if let Some(&kind) = error.downcast_ref::<FailureKind>() {
+            return Some(kind);
+        }
+
+        error
+            .chain()
+            .any(|e| e.is::<tokio_postgres::Error>())
+            .then_some(FailureKind::Database)
+    }
+
+    /// The name the kind is reported as.
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureKind::Config => "config",
+            FailureKind::Upstream => "upstream",
+            FailureKind::SafetyGuard => "safety_guard",
+            FailureKind::Database => "database",
+            FailureKind::Locked => "locked",
+        }
+    }
+
+    /// Whether retrying is unlikely to help, so someone should look at the
+    /// failure right away.
+    pub fn needs_attention(self) -> bool {
+        matches!(self, FailureKind::Config | FailureKind::SafetyGuard)
+    }
+
+    /// The exit code a run that failed this way exits with.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::Config => 2,
+            FailureKind::Upstream => 3,
+            FailureKind::SafetyGuard => 4,
+            FailureKind::Database => 5,
+            FailureKind::Locked => 7,
+        }
+    }
+}
+
+impl Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::Config => "configuration error",
+            FailureKind::Upstream => "upstream unavailable",
+            FailureKind::SafetyGuard => "aborted by safety guard",
+            FailureKind::Database => "database error",
+            FailureKind::Locked => "database locked by another populator",
+        })
+    }
+}
@@ -0,0 +1,229 @@
+//! Recording the responses a run receives, and replaying them later.
+
+use crate::leaderboard_source::{LeaderboardEntry, LeaderboardSource};
+use crate::upstream::Upstream;
+use anyhow::{Context, Error, format_err};
+use futures::{Stream, StreamExt, stream};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::info;
+
+const WORKSHOP_FILE: &str = "workshop.json";
+const LEADERBOARDS_FILE: &str = "leaderboards.json";
+const PERSONA_NAMES_FILE: &str = "persona_names.json";
+
+/// Whether to record the responses `data_collection` receives to a fixture
+/// directory, or to replay them from one instead of using the network.
+#[derive(Debug, Clone)]
+pub enum Fixtures {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// The outcome of a recorded request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Recorded<T> {
+    Ok(T),
+    Err(String),
+}
+
+impl<T: Clone> Recorded<T> {
+    fn new(result: &Result<T, Error>) -> Self {
+        match result {
+            Ok(x) => Recorded::Ok(x.clone()),
+            Err(e) => Recorded::Err(format!("{e:#}")),
+        }
+    }
+
+    fn to_result(&self) -> Result<T, Error> {
+        match self {
+            Recorded::Ok(x) => Ok(x.clone()),
+            Recorded::Err(e) => Err(format_err!("{e} (replayed)")),
+        }
+    }
+}
+
+/// Everything `data_collection` received during a run.
+///
+/// Persona names are recorded per player, since which players are looked up
+/// together depends on hash map ordering.
+#[derive(Debug, Default)]
+struct Recording {
+    workshop_pages: Vec<Recorded<Vec<JsonValue>>>,
+    leaderboards: BTreeMap<String, Recorded<Vec<LeaderboardEntry>>>,
+    persona_names: BTreeMap<u64, Option<String>>,
+}
+
+impl Recording {
+    fn load(dir: &Path) -> Result<Self, Error> {
+        Ok(Recording {
+            workshop_pages: read_json(&dir.join(WORKSHOP_FILE))?,
+            leaderboards: read_json(&dir.join(LEADERBOARDS_FILE))?,
+            persona_names: read_json(&dir.join(PERSONA_NAMES_FILE))?,
+        })
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), Error> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("couldn't create fixture directory {}", dir.display()))?;
+        write_json(&dir.join(WORKSHOP_FILE), &self.workshop_pages)?;
+        write_json(&dir.join(LEADERBOARDS_FILE), &self.leaderboards)?;
+        write_json(&dir.join(PERSONA_NAMES_FILE), &self.persona_names)?;
+
+        Ok(())
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let file =
+        File::open(path).with_context(|| format!("couldn't open fixture {}", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("invalid fixture {}", path.display()))
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<(), Error> {
+    let file = File::create(path)
+        .with_context(|| format!("couldn't create fixture {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, value)
+        .map_err(Error::from)
+        .and_then(|()| Ok(writer.flush()?))
+        .with_context(|| format!("couldn't write fixture {}", path.display()))
+}
+
+/// Passes requests through to another [`Upstream`], recording the responses.
+#[derive(Debug)]
+pub struct Recorder<U> {
+    inner: U,
+    recording: Mutex<Recording>,
+}
+
+impl<U> Recorder<U> {
+    /// Wraps `inner`, starting with an empty recording.
+    pub fn new(inner: U) -> Self {
+        Recorder {
+            inner,
+            recording: Mutex::new(Recording::default()),
+        }
+    }
+
+    /// Writes the responses recorded so far to `dir`.
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        self.recording.lock().unwrap().save(dir)?;
+        info!(dir = %dir.display(), "Saved fixtures");
+
+        Ok(())
+    }
+}
+
+impl<U: LeaderboardSource> LeaderboardSource for Recorder<U> {
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        let result = self.inner.leaderboard_entries_all(leaderboard_name).await;
+        self.recording
+            .lock()
+            .unwrap()
+            .leaderboards
+            .insert(leaderboard_name.to_owned(), Recorded::new(&result));
+
+        result
+    }
+}
+
+impl<U: Upstream> Upstream for Recorder<U> {
+    fn workshop_pages(&self) -> impl Stream<Item = Result<Vec<JsonValue>, Error>> {
+        self.inner.workshop_pages().inspect(|page| {
+            self.recording
+                .lock()
+                .unwrap()
+                .workshop_pages
+                .push(Recorded::new(page));
+        })
+    }
+
+    async fn persona_names(&self, steam_ids: Vec<u64>) -> Result<Vec<Option<String>>, Error> {
+        let names = self.inner.persona_names(steam_ids.clone()).await?;
+        self.recording
+            .lock()
+            .unwrap()
+            .persona_names
+            .extend(steam_ids.into_iter().zip(names.iter().cloned()));
+
+        Ok(names)
+    }
+
+    fn concurrency(&self) -> usize {
+        self.inner.concurrency()
+    }
+}
+
+/// Serves the responses of a recorded run, without using the network.
+#[derive(Debug)]
+pub struct Replay {
+    recording: Recording,
+}
+
+impl Replay {
+    /// Loads the recording saved to `dir`.
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let recording = Recording::load(dir)?;
+        info!(
+            dir = %dir.display(),
+            workshop_pages = recording.workshop_pages.len(),
+            leaderboards = recording.leaderboards.len(),
+            persona_names = recording.persona_names.len(),
+            "Loaded fixtures"
+        );
+
+        Ok(Replay { recording })
+    }
+}
+
+impl LeaderboardSource for Replay {
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        self.recording
+            .leaderboards
+            .get(leaderboard_name)
+            .ok_or_else(|| {
+                format_err!("no recorded response for leaderboard {leaderboard_name:?}")
+            })?
+            .to_result()
+    }
+}
+
+impl Upstream for Replay {
+    fn workshop_pages(&self) -> impl Stream<Item = Result<Vec<JsonValue>, Error>> {
+        stream::iter(&self.recording.workshop_pages).map(Recorded::to_result)
+    }
+
+    async fn persona_names(&self, steam_ids: Vec<u64>) -> Result<Vec<Option<String>>, Error> {
+        steam_ids
+            .into_iter()
+            .map(|steam_id| {
+                self.recording
+                    .persona_names
+                    .get(&steam_id)
+                    .cloned()
+                    .ok_or_else(|| format_err!("no recorded persona name for {steam_id}"))
+            })
+            .collect()
+    }
+
+    /// One at a time, so leaderboards are requested in the same order on
+    /// every replay.
+    fn concurrency(&self) -> usize {
+        1
+    }
+}
@@ -0,0 +1,559 @@
+//! Requests to a pool of DistanceSteamDataServers, with failover.
+
+use crate::leaderboard_source::{LeaderboardEntry, LeaderboardSource};
+use crate::throttle::Throttle;
+use anyhow::{Context, Error, format_err};
+use distance_steam_data_client::Client as GrpcClient;
+use futures::future;
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tap::TapFallible;
+use tokio::time::{self, Instant};
+use tracing::{debug, info, warn};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The player whose name is looked up to check that an unhealthy server has
+/// recovered. Any public profile would do; looking one up goes through the
+/// server's Steam client, so a server whose bot is offline fails it.
+const PROBE_STEAM_ID: u64 = 76561197960287930;
+
+/// How long an unhealthy server is avoided after its first failure. The delay
+/// doubles with every further failure, up to `MAX_RETRY_DELAY`.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// A set of DistanceSteamDataServer endpoints that requests are spread across.
+///
+/// Each request goes to the healthy server with the fewest requests in flight,
+/// and fails over to the next one if it errors. A server that can't be
+/// connected to, or that fails a request, is marked unhealthy and avoided for
+/// a while. After that it is reconnected to and probed with a name lookup,
+/// and only gets requests again once the probe succeeds.
+///
+/// Every request to any of the servers is subject to the same [`Throttle`].
+#[derive(Debug)]
+pub struct GrpcPool<C = GrpcClient> {
+    endpoints: Vec<Endpoint<C>>,
+    throttle: Throttle,
+    /// Rotates the order in which equally loaded servers are picked.
+    next: AtomicUsize,
+}
+
+/// A connection to a DistanceSteamDataServer, as [`GrpcPool`] uses it.
+pub trait Connection: Clone + Sized {
+    /// Connects to the server at `address`.
+    fn connect(address: &str) -> impl Future<Output = Result<Self, Error>>;
+
+    /// Returns all entries of the leaderboard named `leaderboard_name`, best
+    /// first.
+    fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> impl Future<Output = Result<Vec<LeaderboardEntry>, Error>>;
+
+    /// Returns the persona name of each of `steam_ids`, in the same order.
+    fn persona_names(
+        &self,
+        steam_ids: Vec<u64>,
+    ) -> impl Future<Output = Result<Vec<Option<String>>, Error>>;
+}
+
+impl Connection for GrpcClient {
+    async fn connect(address: &str) -> Result<Self, Error> {
+        let client = GrpcClient::connect(address).await?;
+
+        Ok(client)
+    }
+
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        let entries = GrpcClient::leaderboard_entries_all(self, leaderboard_name).await?;
+
+        Ok(entries
+            .into_iter()
+            .map(|entry| LeaderboardEntry {
+                steam_id: entry.steam_id,
+                score: entry.score,
+                has_replay: entry.has_replay,
+            })
+            .collect())
+    }
+
+    async fn persona_names(&self, steam_ids: Vec<u64>) -> Result<Vec<Option<String>>, Error> {
+        let names = GrpcClient::persona_names(self, steam_ids).await?;
+
+        Ok(names)
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint<C> {
+    address: String,
+    in_flight: AtomicUsize,
+    state: Mutex<EndpointState<C>>,
+    /// Held while connecting, so concurrent requests don't all reconnect at
+    /// once.
+    connecting: tokio::sync::Mutex<()>,
+    /// Held while probing, so concurrent requests don't all probe at once.
+    probing: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug)]
+struct EndpointState<C> {
+    client: Option<C>,
+    /// The number of consecutive failures.
+    failures: u32,
+    /// When an unhealthy server may be tried again; `None` while healthy.
+    retry_at: Option<Instant>,
+}
+
+impl<C> Default for EndpointState<C> {
+    fn default() -> Self {
+        EndpointState {
+            client: None,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+}
+
+impl<C: Connection> GrpcPool<C> {
+    /// Connects to all of `addresses`, failing only if none of them can be
+    /// reached.
+    pub async fn connect(addresses: Vec<String>, throttle: Throttle) -> Result<Self, Error> {
+        let pool = GrpcPool {
+            throttle,
+            endpoints: addresses
+                .into_iter()
+                .map(|address| Endpoint {
+                    address,
+                    in_flight: AtomicUsize::new(0),
+                    state: Mutex::new(EndpointState::default()),
+                    connecting: tokio::sync::Mutex::new(()),
+                    probing: tokio::sync::Mutex::new(()),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+        };
+
+        let results = future::join_all(pool.endpoints.iter().map(|endpoint| async move {
+            endpoint
+                .client()
+                .await
+                .tap_ok(|_| info!(address = %endpoint.address, "Connected to Distance gRPC server"))
+                .tap_err(|e| endpoint.failed(e))
+        }))
+        .await;
+        if !results.iter().any(Result::is_ok) {
+            let error = results
+                .into_iter()
+                .filter_map(Result::err)
+                .last()
+                .unwrap_or_else(|| format_err!("no gRPC server addresses were given"));
+            return Err(error.context("couldn't connect to any Distance gRPC server"));
+        }
+
+        Ok(pool)
+    }
+
+    /// How many requests may be in flight at once.
+    pub fn concurrency(&self) -> usize {
+        self.throttle.concurrency()
+    }
+
+    /// Returns the persona name of each of `steam_ids`, in the same order.
+    pub async fn persona_names(&self, steam_ids: Vec<u64>) -> Result<Vec<Option<String>>, Error> {
+        let steam_ids = &steam_ids;
+        self.call(|client| async move { client.persona_names(steam_ids.clone()).await })
+            .await
+    }
+
+    /// Sends `request` to each server in turn, in the order `candidates`
+    /// returns, until one of them completes it. Unhealthy servers that are
+    /// due to be retried are probed first, and skipped if the probe fails.
+    async fn call<T, F, Fut>(&self, request: F) -> Result<T, Error>
+    where
+        F: Fn(C) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut last_error = None;
+        for (endpoint, due) in self.candidates() {
+            let _in_flight = InFlight::new(&endpoint.in_flight);
+            if due && let Err(e) = self.probe(endpoint).await {
+                last_error = Some(e);
+                continue;
+            }
+            let client = match endpoint.client().await {
+                Ok(x) => x,
+                Err(e) => {
+                    endpoint.failed(&e);
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+
+            match self.throttle.run(request(client)).await {
+                Ok(x) => {
+                    endpoint.succeeded();
+                    return Ok(x);
+                }
+                Err(e) => {
+                    let e = e.context(format!("gRPC server {} failed", endpoint.address));
+                    debug!(address = %endpoint.address, "gRPC request failed: {e:#}");
+                    endpoint.failed(&e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.expect("the pool has at least one endpoint"))
+    }
+
+    /// Checks whether the unhealthy `endpoint` has recovered by reconnecting
+    /// to it and looking up a name, and marks it healthy again if it has.
+    async fn probe(&self, endpoint: &Endpoint<C>) -> Result<(), Error> {
+        let _probing = endpoint.probing.lock().await;
+        // Another request may have probed it while this one waited
+        match endpoint.state.lock().unwrap().retry_at {
+            None => return Ok(()),
+            Some(retry_at) if retry_at > Instant::now() => {
+                return Err(format_err!(
+                    "gRPC server {} failed a health probe",
+                    endpoint.address
+                ));
+            }
+            Some(_) => {}
+        }
+
+        debug!(address = %endpoint.address, "Probing unhealthy gRPC server");
+        let result = async {
+            let client = endpoint.client().await?;
+            time::timeout(
+                PROBE_TIMEOUT,
+                self.throttle
+                    .run(client.persona_names(vec![PROBE_STEAM_ID])),
+            )
+            .await
+            .context("timed out")??;
+            Ok::<_, Error>(())
+        }
+        .await
+        .with_context(|| format!("gRPC server {} failed a health probe", endpoint.address));
+
+        match result {
+            Ok(_) => {
+                endpoint.succeeded();
+                Ok(())
+            }
+            Err(e) => {
+                endpoint.failed(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the servers to try for a request, in order: unhealthy servers
+    /// that are due to be probed, healthy servers from the least loaded, and
+    /// then the remaining unhealthy servers, as a last resort. Each comes with
+    /// whether it should be probed before it's sent the request.
+    fn candidates(&self) -> Vec<(&Endpoint<C>, bool)> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let n = self.endpoints.len();
+        let mut healthy = Vec::with_capacity(n);
+        let mut unhealthy = Vec::new();
+        for i in 0..n {
+            let endpoint = &self.endpoints[(start + i) % n];
+            match endpoint.state.lock().unwrap().retry_at {
+                None => healthy.push(endpoint),
+                Some(retry_at) => unhealthy.push((retry_at, endpoint)),
+            }
+        }
+
+        // Stable sorts, so ties keep the rotated order
+        healthy.sort_by_key(|endpoint| endpoint.in_flight.load(Ordering::Relaxed));
+        unhealthy.sort_by_key(|&(retry_at, _)| retry_at);
+
+        let now = Instant::now();
+        let (due, not_due): (Vec<_>, Vec<_>) = unhealthy
+            .into_iter()
+            .map(|(retry_at, endpoint)| (endpoint, retry_at <= now))
+            .partition(|&(_, due)| due);
+
+        due.into_iter()
+            .chain(healthy.into_iter().map(|endpoint| (endpoint, false)))
+            .chain(not_due)
+            .collect()
+    }
+}
+
+impl<C: Connection> LeaderboardSource for GrpcPool<C> {
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        self.call(|client| async move { client.leaderboard_entries_all(leaderboard_name).await })
+            .await
+    }
+}
+
+impl<C: Connection> Endpoint<C> {
+    /// Returns the client for this server, connecting first if needed.
+    async fn client(&self) -> Result<C, Error> {
+        if let Some(client) = &self.state.lock().unwrap().client {
+            return Ok(client.clone());
+        }
+
+        let _connecting = self.connecting.lock().await;
+        if let Some(client) = &self.state.lock().unwrap().client {
+            return Ok(client.clone());
+        }
+
+        let context = || format!("error connecting to gRPC server {}", self.address);
+        let client = time::timeout(CONNECT_TIMEOUT, C::connect(&self.address))
+            .await
+            .with_context(context)?
+            .with_context(context)?;
+        self.state.lock().unwrap().client = Some(client.clone());
+
+        Ok(client)
+    }
+
+    fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.retry_at.is_some() {
+            info!(address = %self.address, "gRPC server is healthy again");
+        }
+        state.failures = 0;
+        state.retry_at = None;
+    }
+
+    fn failed(&self, error: &Error) {
+        let mut state = self.state.lock().unwrap();
+        state.client = None;
+        state.failures += 1;
+        let delay = MIN_RETRY_DELAY
+            .saturating_mul(1 << (state.failures - 1).min(16))
+            .min(MAX_RETRY_DELAY);
+        if state.retry_at.is_none() {
+            warn!(
+                address = %self.address,
+                retry_in_secs = delay.as_secs(),
+                "gRPC server is unhealthy: {error:#}"
+            );
+        }
+        state.retry_at = Some(Instant::now() + delay);
+    }
+}
+
+/// Counts a request as in flight on a server until dropped.
+#[derive(Debug)]
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        InFlight(counter)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::throttle::ThrottleConfig;
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, LazyLock};
+
+    /// The fake servers, by address. Each test uses addresses of its own.
+    static SERVERS: LazyLock<Mutex<HashMap<String, Arc<FakeServer>>>> =
+        LazyLock::new(Mutex::default);
+
+    /// A DistanceSteamDataServer that answers every request, unless it's
+    /// down.
+    #[derive(Debug, Default)]
+    struct FakeServer {
+        down: AtomicBool,
+        /// The requests it was sent, in order, including failed ones.
+        requests: Mutex<Vec<&'static str>>,
+    }
+
+    impl FakeServer {
+        fn handle(&self, request: &'static str) -> Result<(), Error> {
+            self.requests.lock().unwrap().push(request);
+            if self.down.load(Ordering::Relaxed) {
+                return Err(format_err!("the server is down"));
+            }
+
+            Ok(())
+        }
+
+        fn set_down(&self, down: bool) {
+            self.down.store(down, Ordering::Relaxed);
+        }
+
+        fn requests(&self) -> Vec<&'static str> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    struct FakeConnection(Arc<FakeServer>);
+
+    impl Connection for FakeConnection {
+        async fn connect(address: &str) -> Result<Self, Error> {
+            let server = Arc::clone(&SERVERS.lock().unwrap()[address]);
+            server.handle("connect")?;
+
+            Ok(FakeConnection(server))
+        }
+
+        async fn leaderboard_entries_all(
+            &self,
+            _leaderboard_name: &str,
+        ) -> Result<Vec<LeaderboardEntry>, Error> {
+            self.0.handle("leaderboard")?;
+
+            Ok(vec![LeaderboardEntry {
+                steam_id: 1,
+                score: 100,
+                has_replay: false,
+            }])
+        }
+
+        async fn persona_names(&self, steam_ids: Vec<u64>) -> Result<Vec<Option<String>>, Error> {
+            let request = if steam_ids == [PROBE_STEAM_ID] {
+                "probe"
+            } else {
+                "persona_names"
+            };
+            self.0.handle(request)?;
+
+            Ok(steam_ids
+                .iter()
+                .map(|_| Some("Player".to_owned()))
+                .collect())
+        }
+    }
+
+    /// Starts `n` servers for the test `test`, and returns their addresses
+    /// along with them.
+    fn servers(test: &str, n: usize) -> (Vec<String>, Vec<Arc<FakeServer>>) {
+        let mut registry = SERVERS.lock().unwrap();
+        (0..n)
+            .map(|i| {
+                let address = format!("{test}-{i}");
+                let server = Arc::new(FakeServer::default());
+                registry.insert(address.clone(), Arc::clone(&server));
+                (address, server)
+            })
+            .unzip()
+    }
+
+    async fn pool(addresses: Vec<String>) -> Result<GrpcPool<FakeConnection>, Error> {
+        let throttle = Throttle::new(ThrottleConfig {
+            concurrency: 4,
+            max_rate: 1000.0,
+            slow_request: Duration::from_secs(10),
+        });
+
+        GrpcPool::connect(addresses, throttle).await
+    }
+
+    /// Requests a leaderboard from `pool` `n` times, which should all succeed.
+    async fn request(pool: &GrpcPool<FakeConnection>, n: usize) {
+        for _ in 0..n {
+            pool.leaderboard_entries_all("Broken Symmetry_1_stable")
+                .await
+                .unwrap();
+        }
+    }
+
+    fn count(server: &FakeServer, request: &str) -> usize {
+        server.requests().iter().filter(|&&x| x == request).count()
+    }
+
+    #[tokio::test]
+    async fn connecting_fails_only_if_no_server_is_reachable() {
+        let (addresses, servers) = servers("connect", 2);
+        servers[0].set_down(true);
+        let pool = pool(addresses.clone()).await.unwrap();
+        request(&pool, 2).await;
+        assert_eq!(count(&servers[1], "leaderboard"), 2);
+
+        servers[1].set_down(true);
+        assert!(self::pool(addresses).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_requests_fail_over_and_mark_the_server_unhealthy() {
+        let (addresses, servers) = servers("fail-over", 2);
+        let pool = pool(addresses).await.unwrap();
+        servers[0].set_down(true);
+
+        request(&pool, 4).await;
+        assert_eq!(count(&servers[0], "leaderboard"), 1);
+        assert_eq!(count(&servers[1], "leaderboard"), 4);
+
+        // Even once it's back up, it's left alone until it's due to be probed
+        servers[0].set_down(false);
+        request(&pool, 4).await;
+        assert_eq!(count(&servers[0], "leaderboard"), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unhealthy_servers_are_probed_before_getting_requests_again() {
+        let (addresses, servers) = servers("probe", 2);
+        let pool = pool(addresses).await.unwrap();
+        servers[0].set_down(true);
+        request(&pool, 1).await;
+        servers[0].set_down(false);
+
+        time::advance(MIN_RETRY_DELAY).await;
+        request(&pool, 1).await;
+        assert_eq!(
+            servers[0].requests(),
+            ["connect", "leaderboard", "connect", "probe", "leaderboard"]
+        );
+
+        // It's healthy again, so it shares the load
+        request(&pool, 4).await;
+        assert_eq!(count(&servers[0], "leaderboard"), 4);
+        assert_eq!(count(&servers[0], "probe"), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_probes_double_the_wait() {
+        let (addresses, servers) = servers("failed-probe", 2);
+        let pool = pool(addresses).await.unwrap();
+        servers[0].set_down(true);
+        request(&pool, 1).await;
+
+        time::advance(MIN_RETRY_DELAY).await;
+        request(&pool, 1).await;
+        // Reconnecting failed, so there was nothing to probe
+        assert_eq!(servers[0].requests(), ["connect", "leaderboard", "connect"]);
+
+        servers[0].set_down(false);
+        time::advance(MIN_RETRY_DELAY).await;
+        request(&pool, 2).await;
+        assert_eq!(count(&servers[0], "probe"), 0);
+
+        time::advance(MIN_RETRY_DELAY).await;
+        request(&pool, 1).await;
+        assert_eq!(count(&servers[0], "probe"), 1);
+        assert_eq!(count(&servers[0], "leaderboard"), 2);
+        assert_eq!(count(&servers[1], "leaderboard"), 4);
+    }
+}
@@ -0,0 +1,97 @@
+//! Where leaderboard entries are downloaded from.
+
+use crate::grpc_pool::GrpcPool;
+use crate::steam_community::SteamCommunity;
+use anyhow::Error;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, warn};
+
+/// An entry of a leaderboard, in the shape every source returns it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub steam_id: u64,
+    pub score: i32,
+    pub has_replay: bool,
+}
+
+/// Somewhere leaderboard entries can be downloaded from.
+pub trait LeaderboardSource {
+    /// Returns all entries of the leaderboard named `leaderboard_name`, as
+    /// created by `distance_util::create_leaderboard_name_string`, best first.
+    fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> impl Future<Output = Result<Vec<LeaderboardEntry>, Error>>;
+}
+
+/// Which [`Leaderboards`] to use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum LeaderboardSourceKind {
+    /// Only the DistanceSteamDataServers.
+    Grpc,
+    /// Only Steam Community's public XML leaderboards.
+    SteamCommunity,
+    /// The DistanceSteamDataServers, falling back to Steam Community for
+    /// leaderboards they fail to return.
+    Auto,
+}
+
+/// The leaderboard source chosen for a run.
+#[derive(Debug)]
+pub enum Leaderboards<'a> {
+    Grpc(&'a GrpcPool),
+    SteamCommunity(SteamCommunity),
+    Auto {
+        grpc: &'a GrpcPool,
+        fallback: SteamCommunity,
+        /// Whether the fallback has been used yet, to only warn about it once.
+        fell_back: AtomicBool,
+    },
+}
+
+impl<'a> Leaderboards<'a> {
+    /// Returns the `kind` of source, built from `grpc` and `community`.
+    pub fn new(kind: LeaderboardSourceKind, grpc: &'a GrpcPool, community: SteamCommunity) -> Self {
+        match kind {
+            LeaderboardSourceKind::Grpc => Leaderboards::Grpc(grpc),
+            LeaderboardSourceKind::SteamCommunity => Leaderboards::SteamCommunity(community),
+            LeaderboardSourceKind::Auto => Leaderboards::Auto {
+                grpc,
+                fallback: community,
+                fell_back: AtomicBool::new(false),
+            },
+        }
+    }
+}
+
+impl LeaderboardSource for Leaderboards<'_> {
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        match self {
+            Leaderboards::Grpc(grpc) => grpc.leaderboard_entries_all(leaderboard_name).await,
+            Leaderboards::SteamCommunity(community) => {
+                community.leaderboard_entries_all(leaderboard_name).await
+            }
+            Leaderboards::Auto {
+                grpc,
+                fallback,
+                fell_back,
+            } => match grpc.leaderboard_entries_all(leaderboard_name).await {
+                Ok(x) => Ok(x),
+                Err(e) => {
+                    if !fell_back.swap(true, Ordering::Relaxed) {
+                        warn!("Falling back to Steam Community leaderboards: {e:#}");
+                    } else {
+                        debug!(leaderboard = %leaderboard_name, "Falling back to Steam Community: {e:#}");
+                    }
+
+                    fallback.leaderboard_entries_all(leaderboard_name).await
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! The Distance Database's data model, and the logic that populates it from
+//! Steam.
+//!
+//! A run has three steps, each usable on its own:
+//!
+//! 1. [`data_collection::run`] downloads everything from an
+//!    [`Upstream`](upstream::Upstream) into a [`DistanceData`](common::DistanceData),
+//!    ranking leaderboard entries with [`ranking::rank`].
+//! 2. [`safety_guard::check`] compares it with what's stored, refusing data
+//!    that looks broken.
+//! 3. [`data_storing::run`] writes it to the database, or
+//!    [`data_storing::store`] to any [`Storage`](storage::Storage), such as a
+//!    SQLite copy.
+//!
+//! [`populate::run`] does all three the way the `distance-db-populator` binary
+//! does, and is what the manager calls to populate in-process.
+
+#![warn(
+    rust_2018_idioms,
+    deprecated_in_future,
+    macro_use_extern_crate,
+    missing_debug_implementations,
+    unused_qualifications
+)]
+
+pub mod anonymize;
+pub mod common;
+pub mod data_collection;
+pub mod data_storing;
+pub mod db;
+pub mod dump;
+pub mod failure;
+pub mod fixtures;
+pub mod grpc_pool;
+pub mod leaderboard_source;
+pub mod lock;
+pub mod logging;
+pub mod opt_out;
+pub mod parquet_export;
+pub mod populate;
+pub mod postgres_storage;
+pub mod ranking;
+pub mod report;
+pub mod safety_guard;
+pub mod sqlite_storage;
+pub mod static_api;
+pub mod steam_community;
+pub mod storage;
+pub mod synthetic;
+pub mod throttle;
+pub mod upstream;
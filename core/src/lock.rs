@@ -0,0 +1,118 @@
+//! The database lock that keeps populators from running at the same time.
+
+use crate::failure::FailureKind;
+use anyhow::{Error, format_err};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
+use tokio::time;
+use tracing::info;
+
+/// The key of the session-level advisory lock that keeps populators from
+/// writing to the database at the same time ("distance" in ASCII).
+const LOCK_KEY: i64 = 0x6469_7374_616e_6365;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Returns the `application_name` the populator connects with, which tells
+/// other populators waiting for the lock who holds it.
+pub fn application_name() -> String {
+    let hostname = env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned());
+
+    format!(
+        "distance-db-populator host={hostname} pid={}",
+        process::id()
+    )
+}
+
+/// Takes the populator lock, which is held until the connection closes.
+///
+/// If another session holds the lock, waits up to `wait` for it to be
+/// released before giving up.
+pub async fn acquire(db: &tokio_postgres::Client, wait: Duration) -> Result<(), Error> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let acquired: bool = db
+            .query_one("SELECT pg_try_advisory_lock($1)", &[&LOCK_KEY])
+            .await?
+            .get(0);
+        if acquired {
+            info!("Acquired the populator lock");
+            return Ok(());
+        }
+
+        let holder = lock_holder(db).await?;
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(
+                format_err!("the populator lock is held by {holder}").context(FailureKind::Locked)
+            );
+        }
+
+        info!(%holder, "Waiting for the populator lock");
+        time::sleep(POLL_INTERVAL.min(deadline - now)).await;
+    }
+}
+
+/// The session holding the populator lock.
+#[derive(Debug)]
+enum LockHolder {
+    Session {
+        application_name: String,
+        client: Option<String>,
+        backend_pid: i32,
+        since: String,
+    },
+    /// The lock was released before the holder could be looked up.
+    Unknown,
+}
+
+impl Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockHolder::Session {
+                application_name,
+                client,
+                backend_pid,
+                since,
+            } => {
+                write!(f, "`{application_name}`")?;
+                if let Some(client) = client {
+                    write!(f, " connected from {client}")?;
+                }
+                write!(f, " (backend PID {backend_pid}, connected since {since})")
+            }
+            LockHolder::Unknown => f.write_str("an unknown session"),
+        }
+    }
+}
+
+async fn lock_holder(db: &tokio_postgres::Client) -> Result<LockHolder, Error> {
+    // A bigint advisory lock key is split across `classid` and `objid`.
+    let row = db
+        .query_opt(
+            "SELECT a.application_name, host(a.client_addr), a.pid, a.backend_start::text
+             FROM pg_locks l JOIN pg_stat_activity a ON a.pid = l.pid
+             WHERE l.locktype = 'advisory' AND l.granted
+               AND l.classid = ($1::bigint >> 32)::oid
+               AND l.objid = ($1::bigint & 4294967295)::oid
+               AND l.objsubid = 1",
+            &[&LOCK_KEY],
+        )
+        .await?;
+
+    Ok(match row {
+        Some(row) => LockHolder::Session {
+            application_name: row.get(0),
+            client: row.get(1),
+            backend_pid: row.get(2),
+            since: row.get(3),
+        },
+        None => LockHolder::Unknown,
+    })
+}
@@ -0,0 +1,152 @@
+//! Logging, tracing and progress bars.
+
+use anyhow::{Context, Error};
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+static PROGRESS_BARS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// How log lines are written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// The binary that's logging.
+#[derive(Debug, Copy, Clone)]
+pub struct Service {
+    /// The package name, exported as the service name of traces.
+    pub name: &'static str,
+    /// The crate name, which is the target of the binary's own spans.
+    pub target: &'static str,
+}
+
+/// Keeps the OTLP exporter alive; dropping it flushes any spans that haven't
+/// been exported yet.
+#[derive(Debug)]
+pub struct Telemetry {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = &self.tracer_provider
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("error flushing traces: {e}");
+        }
+    }
+}
+
+/// Installs the global tracing subscriber. The log level is taken from
+/// `RUST_LOG`, defaulting to `info`.
+///
+/// Closing spans are logged with their duration, so every phase of a run shows
+/// up along with how long it took. Progress bars are only drawn for text logs
+/// on a terminal.
+///
+/// If `OTEL_EXPORTER_OTLP_ENDPOINT` or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is
+/// set, spans are also exported to that OTLP/HTTP collector, as
+/// [`otel_layer`] does.
+pub fn init(format: LogFormat, service: Service) -> Result<Telemetry, Error> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let fmt_layer = match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_span_events(FmtSpan::CLOSE)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_events(FmtSpan::CLOSE)
+            .boxed(),
+    };
+
+    let tracer_provider = if otlp_endpoint_configured() {
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .build()
+            .context("error creating the OTLP exporter")?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name(service.name).build())
+            .build();
+
+        Some(provider)
+    } else {
+        None
+    };
+    let otel_layer = tracer_provider
+        .as_ref()
+        .map(|provider| otel_layer(provider, service));
+
+    Registry::default()
+        .with(fmt_layer.with_filter(filter))
+        .with(otel_layer)
+        .init();
+
+    PROGRESS_BARS_ENABLED.store(
+        format == LogFormat::Text && io::stdout().is_terminal(),
+        Ordering::Relaxed,
+    );
+
+    Ok(Telemetry { tracer_provider })
+}
+
+/// Returns a layer exporting spans to `provider`. Exported spans are those of
+/// `service` and of this library, including those at the debug level, e.g.
+/// one per gRPC call, regardless of `RUST_LOG`.
+pub fn otel_layer<S>(provider: &SdkTracerProvider, service: Service) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(service.name))
+        .with_filter(
+            Targets::new()
+                .with_target(service.target, Level::DEBUG)
+                .with_target(env!("CARGO_CRATE_NAME"), Level::DEBUG),
+        )
+}
+
+fn otlp_endpoint_configured() -> bool {
+    [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ]
+    .iter()
+    .any(|name| env::var_os(name).is_some_and(|x| !x.is_empty()))
+}
+
+/// Returns a progress bar, hidden if progress bars are disabled.
+pub fn progress_bar(len: u64) -> ProgressBar {
+    hide_if_disabled(ProgressBar::new(len))
+}
+
+/// Returns a spinner, hidden if progress bars are disabled.
+pub fn spinner() -> ProgressBar {
+    hide_if_disabled(ProgressBar::new_spinner())
+}
+
+fn hide_if_disabled(pb: ProgressBar) -> ProgressBar {
+    if !PROGRESS_BARS_ENABLED.load(Ordering::Relaxed) {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    pb
+}
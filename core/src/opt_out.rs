@@ -0,0 +1,106 @@
+//! The players who asked for their name to be hidden.
+//!
+//! Their Steam IDs are kept in the `name_opt_outs` table. Their leaderboard
+//! entries are stored as usual, but their name is stored as [`HIDDEN_NAME`],
+//! so who is on the list can be told from the stored names.
+//! [`data_storing::run`](crate::data_storing::run) hides them in the data it's
+//! given, so whatever is made from that data afterwards hides them too.
+//!
+//! [`purge`] only changes the database, and [`purge_sqlite`] a SQLite copy.
+//! Parquet exports, dumps and the static API keep the old names until they're
+//! made again.
+//!
+//! Only the current name of a player is ever stored, so there's no history of
+//! names to hide.
+
+use crate::common::User;
+use anyhow::{Context, Error};
+use std::collections::HashSet;
+use tokio_postgres::Client;
+
+/// The name stored for players on the opt-out list.
+pub const HIDDEN_NAME: &str = "[name hidden]";
+
+/// Creates the `name_opt_outs` table unless it exists already, for databases
+/// created before the list existed.
+pub async fn create_table(db: &Client) -> Result<(), Error> {
+    db.batch_execute(
+        "CREATE TABLE IF NOT EXISTS name_opt_outs (steam_id bigint PRIMARY KEY CHECK (steam_id <> 0), added timestamp with time zone NOT NULL DEFAULT now())",
+    )
+    .await
+    .context("error creating the name opt-out list")?;
+
+    Ok(())
+}
+
+/// Returns the Steam IDs on the opt-out list.
+pub async fn list(db: &Client) -> Result<HashSet<u64>, Error> {
+    let rows = db.query("SELECT steam_id FROM name_opt_outs", &[]).await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| row.get::<_, i64>(0) as u64)
+        .collect())
+}
+
+/// Adds `steam_ids` to the opt-out list, and returns how many weren't on it
+/// already. Names stored before are kept until [`purge`] is called.
+pub async fn add(db: &Client, steam_ids: &[u64]) -> Result<u64, Error> {
+    let steam_ids: Vec<i64> = steam_ids.iter().map(|&id| id as i64).collect();
+    let added = db
+        .execute(
+            "INSERT INTO name_opt_outs (steam_id) SELECT unnest($1::bigint[]) ON CONFLICT (steam_id) DO NOTHING",
+            &[&steam_ids],
+        )
+        .await?;
+
+    Ok(added)
+}
+
+/// Removes `steam_ids` from the opt-out list, and returns how many were on
+/// it. Their names are stored again by the next run that looks them up.
+pub async fn remove(db: &Client, steam_ids: &[u64]) -> Result<u64, Error> {
+    let steam_ids: Vec<i64> = steam_ids.iter().map(|&id| id as i64).collect();
+    let removed = db
+        .execute(
+            "DELETE FROM name_opt_outs WHERE steam_id = ANY($1)",
+            &[&steam_ids],
+        )
+        .await?;
+
+    Ok(removed)
+}
+
+/// Replaces the stored names of everyone on the opt-out list with
+/// [`HIDDEN_NAME`], and returns how many names were replaced.
+pub async fn purge(db: &Client) -> Result<u64, Error> {
+    let purged = db
+        .execute(
+            "UPDATE users SET name = $1 WHERE name <> $1 AND steam_id IN (SELECT steam_id FROM name_opt_outs)",
+            &[&HIDDEN_NAME],
+        )
+        .await?;
+
+    Ok(purged)
+}
+
+/// Does what [`purge`] does to a SQLite copy of the database, for the players
+/// `steam_ids`.
+pub fn purge_sqlite(db: &rusqlite::Connection, steam_ids: &HashSet<u64>) -> Result<u64, Error> {
+    let mut stmt = db.prepare("UPDATE users SET name = ?1 WHERE name <> ?1 AND steam_id = ?2")?;
+    let mut purged = 0;
+    for &steam_id in steam_ids {
+        purged += stmt.execute(rusqlite::params![HIDDEN_NAME, steam_id as i64])? as u64;
+    }
+
+    Ok(purged)
+}
+
+/// Hides the names of those of `users` in `opt_outs`.
+pub fn hide_names(users: &mut [User], opt_outs: &HashSet<u64>) {
+    for user in users {
+        if opt_outs.contains(&user.steam_id) {
+            HIDDEN_NAME.clone_into(&mut user.name);
+        }
+    }
+}
@@ -0,0 +1,328 @@
+//! Exporting the dataset as Parquet files, for data science tools.
+//!
+//! The files and their columns are part of the export's interface, and only
+//! change along with [`SCHEMA_VERSION`], which every file carries in its
+//! `distance_db_schema_version` metadata key:
+//!
+//! - `levels.parquet`: `id`, `name`, `is_sprint`, `is_challenge`, `is_stunt`,
+//!   `is_workshop`
+//! - `users.parquet`: `steam_id`, `name`
+//! - `workshop_level_details.parquet`: `level_id`, `title`,
+//!   `author_steam_id`, `filename`, `file_size`, `tags`, `time_created`,
+//!   `time_updated`, `views`, `subscriptions`, `favorited`,
+//!   `lifetime_subscriptions`, `lifetime_favorited`, and the whole details as
+//!   JSON in `raw_details`
+//! - `leaderboard_entries.parquet`: `mode`, `level_id`, `steam_id`, `time`
+//!   (sprint and challenge), `score` (stunt), `rank`, `has_replay`
+//!
+//! When partitioned by mode, the entries are instead written to
+//! `leaderboard_entries/mode=<mode>/part-0.parquet`, Hive style, without the
+//! `mode` column.
+
+use crate::common::DistanceData;
+use crate::storage::LeaderboardEntries;
+use anyhow::{Context, Error};
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{
+    ArrayRef, BooleanArray, Int32Array, Int64Array, RecordBatch, StringArray, TimestampSecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use parquet::format::KeyValue;
+use serde_json::Value as JsonValue;
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info;
+
+/// The version of the files' schema, bumped whenever a column is changed or
+/// removed.
+pub const SCHEMA_VERSION: &str = "1";
+
+/// How to lay out the exported files.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ParquetOptions {
+    /// Write each mode's leaderboard entries to a partition of their own.
+    pub partition_by_mode: bool,
+}
+
+/// Writes `data` to Parquet files in `dir`, creating it if needed and
+/// replacing any files exported before.
+pub fn write(data: &DistanceData, dir: &Path, options: ParquetOptions) -> Result<(), Error> {
+    info!(dir = %dir.display(), "Exporting Parquet files");
+    fs::create_dir_all(dir)
+        .with_context(|| format!("error creating directory {}", dir.display()))?;
+
+    write_file(&dir.join("levels.parquet"), &levels_batch(data)?)?;
+    write_file(&dir.join("users.parquet"), &users_batch(data)?)?;
+    write_file(
+        &dir.join("workshop_level_details.parquet"),
+        &workshop_level_details_batch(data)?,
+    )?;
+
+    // Each mode's entries, in order of level
+    let mut modes: [EntryColumns; 3] = Default::default();
+    for level in &data.levels {
+        for (columns, entries) in modes.iter_mut().zip(LeaderboardEntries::of_level(level)) {
+            columns.push(level.id, entries);
+        }
+    }
+
+    if options.partition_by_mode {
+        for (columns, mode) in modes.into_iter().zip(["sprint", "challenge", "stunt"]) {
+            let partition = dir.join("leaderboard_entries").join(format!("mode={mode}"));
+            fs::create_dir_all(&partition)
+                .with_context(|| format!("error creating directory {}", partition.display()))?;
+            write_file(&partition.join("part-0.parquet"), &columns.batch(false)?)?;
+        }
+    } else {
+        let [sprint, challenge, stunt] = modes;
+        write_file(
+            &dir.join("leaderboard_entries.parquet"),
+            &sprint.append(challenge).append(stunt).batch(true)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes `batch` to `path`, through a temporary file so an interrupted export
+/// doesn't leave a truncated file behind.
+fn write_file(path: &Path, batch: &RecordBatch) -> Result<(), Error> {
+    let temp_path = path.with_extension("parquet.tmp");
+    let file = File::create(&temp_path)
+        .with_context(|| format!("error creating {}", temp_path.display()))?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_key_value_metadata(Some(vec![KeyValue::new(
+            "distance_db_schema_version".to_owned(),
+            SCHEMA_VERSION.to_owned(),
+        )]))
+        .build();
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(properties))?;
+    writer.write(batch)?;
+    writer.close()?;
+
+    fs::rename(&temp_path, path).with_context(|| format!("error writing {}", path.display()))?;
+
+    Ok(())
+}
+
+fn levels_batch(data: &DistanceData) -> Result<RecordBatch, Error> {
+    let levels = &data.levels;
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("is_sprint", DataType::Boolean, false),
+        Field::new("is_challenge", DataType::Boolean, false),
+        Field::new("is_stunt", DataType::Boolean, false),
+        Field::new("is_workshop", DataType::Boolean, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(levels.iter().map(|l| l.id))),
+        Arc::new(StringArray::from_iter_values(
+            levels.iter().map(|l| &l.name),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            levels.iter().map(|l| Some(l.is_sprint)),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            levels.iter().map(|l| Some(l.is_challenge)),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            levels.iter().map(|l| Some(l.is_stunt)),
+        )),
+        Arc::new(BooleanArray::from_iter(
+            levels
+                .iter()
+                .map(|l| Some(l.workshop_level_details.is_some())),
+        )),
+    ];
+
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+fn users_batch(data: &DistanceData) -> Result<RecordBatch, Error> {
+    let users = &data.users;
+    let schema = Schema::new(vec![
+        Field::new("steam_id", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(
+            users.iter().map(|u| u.steam_id as i64),
+        )),
+        Arc::new(StringArray::from_iter_values(users.iter().map(|u| &u.name))),
+    ];
+
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+fn workshop_level_details_batch(data: &DistanceData) -> Result<RecordBatch, Error> {
+    let details: Vec<_> = data
+        .levels
+        .iter()
+        .filter_map(|level| Some((level.id, level.workshop_level_details.as_ref()?)))
+        .collect();
+    let counter = |field: &'static str| -> ArrayRef {
+        Arc::new(Int64Array::from_iter(
+            details.iter().map(|(_, (_, json))| json_i64(json, field)),
+        ))
+    };
+    let timestamp = |field: &'static str| -> ArrayRef {
+        Arc::new(
+            TimestampSecondArray::from_iter(
+                details.iter().map(|(_, (_, json))| json_i64(json, field)),
+            )
+            .with_timezone("UTC"),
+        )
+    };
+
+    let mut tags = ListBuilder::new(StringBuilder::new());
+    for (_, (parsed, _)) in &details {
+        for tag in &parsed.tags {
+            tags.values().append_value(&tag.tag);
+        }
+        tags.append(true);
+    }
+
+    let schema = Schema::new(vec![
+        Field::new("level_id", DataType::Int64, false),
+        Field::new("title", DataType::Utf8, false),
+        Field::new("author_steam_id", DataType::Int64, false),
+        Field::new("filename", DataType::Utf8, false),
+        Field::new("file_size", DataType::Int64, false),
+        Field::new(
+            "tags",
+            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            false,
+        ),
+        Field::new(
+            "time_created",
+            DataType::Timestamp(TimeUnit::Second, Some("UTC".into())),
+            true,
+        ),
+        Field::new(
+            "time_updated",
+            DataType::Timestamp(TimeUnit::Second, Some("UTC".into())),
+            true,
+        ),
+        Field::new("views", DataType::Int64, true),
+        Field::new("subscriptions", DataType::Int64, true),
+        Field::new("favorited", DataType::Int64, true),
+        Field::new("lifetime_subscriptions", DataType::Int64, true),
+        Field::new("lifetime_favorited", DataType::Int64, true),
+        Field::new("raw_details", DataType::Utf8, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(
+            details.iter().map(|(id, _)| *id),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            details.iter().map(|(_, (parsed, _))| &parsed.title),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            details.iter().map(|(_, (parsed, _))| parsed.creator as i64),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            details.iter().map(|(_, (parsed, _))| &parsed.filename),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            details.iter().map(|(_, (parsed, _))| parsed.file_size),
+        )),
+        Arc::new(tags.finish()),
+        timestamp("time_created"),
+        timestamp("time_updated"),
+        counter("views"),
+        counter("subscriptions"),
+        counter("favorited"),
+        counter("lifetime_subscriptions"),
+        counter("lifetime_favorited"),
+        Arc::new(StringArray::from_iter_values(
+            details.iter().map(|(_, (_, json))| json.to_string()),
+        )),
+    ];
+
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+/// Returns the integer `field` of a workshop level's details, which the Steam
+/// Web API returns as either a number or a string, depending on the field.
+fn json_i64(json: &JsonValue, field: &str) -> Option<i64> {
+    match json.get(field)? {
+        JsonValue::Number(n) => n.as_i64(),
+        JsonValue::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// The columns of leaderboard entries, built up a leaderboard at a time.
+#[derive(Debug, Default)]
+struct EntryColumns {
+    mode: Vec<&'static str>,
+    level_id: Vec<i64>,
+    steam_id: Vec<i64>,
+    time: Vec<Option<i32>>,
+    score: Vec<Option<i32>>,
+    rank: Vec<i32>,
+    has_replay: Vec<bool>,
+}
+
+impl EntryColumns {
+    fn push(&mut self, level_id: i64, entries: LeaderboardEntries<'_>) {
+        let is_stunt = matches!(entries, LeaderboardEntries::Stunt(_));
+        for (steam_id, value, rank, has_replay) in entries.rows() {
+            self.mode.push(entries.mode());
+            self.level_id.push(level_id);
+            self.steam_id.push(steam_id);
+            self.time.push((!is_stunt).then_some(value));
+            self.score.push(is_stunt.then_some(value));
+            self.rank.push(rank);
+            self.has_replay.push(has_replay);
+        }
+    }
+
+    fn append(mut self, other: EntryColumns) -> Self {
+        self.mode.extend(other.mode);
+        self.level_id.extend(other.level_id);
+        self.steam_id.extend(other.steam_id);
+        self.time.extend(other.time);
+        self.score.extend(other.score);
+        self.rank.extend(other.rank);
+        self.has_replay.extend(other.has_replay);
+
+        self
+    }
+
+    fn batch(self, with_mode: bool) -> Result<RecordBatch, Error> {
+        let mut fields = Vec::new();
+        let mut columns: Vec<ArrayRef> = Vec::new();
+        if with_mode {
+            fields.push(Field::new("mode", DataType::Utf8, false));
+            columns.push(Arc::new(StringArray::from(self.mode)));
+        }
+        fields.extend([
+            Field::new("level_id", DataType::Int64, false),
+            Field::new("steam_id", DataType::Int64, false),
+            Field::new("time", DataType::Int32, true),
+            Field::new("score", DataType::Int32, true),
+            Field::new("rank", DataType::Int32, false),
+            Field::new("has_replay", DataType::Boolean, false),
+        ]);
+        columns.extend([
+            Arc::new(Int64Array::from(self.level_id)) as ArrayRef,
+            Arc::new(Int64Array::from(self.steam_id)),
+            Arc::new(Int32Array::from(self.time)),
+            Arc::new(Int32Array::from(self.score)),
+            Arc::new(Int32Array::from(self.rank)),
+            Arc::new(BooleanArray::from(self.has_replay)),
+        ]);
+
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
@@ -0,0 +1,367 @@
+//! A whole populator run: collecting data from Steam, checking it, and
+//! storing it. The `distance-db-populator` binary is a thin wrapper around
+//! [`run`], which the manager can also call to populate in-process.
+
+use crate::common::{DistanceData, RunOptions};
+use crate::failure::FailureKind;
+use crate::fixtures::{Fixtures, Recorder, Replay};
+use crate::grpc_pool::GrpcPool;
+use crate::leaderboard_source::{LeaderboardSourceKind, Leaderboards};
+use crate::logging::LogFormat;
+use crate::parquet_export::{self, ParquetOptions};
+use crate::report::RunReport;
+use crate::sqlite_storage::SqliteStorage;
+use crate::steam_community::SteamCommunity;
+use crate::throttle::{Throttle, ThrottleConfig};
+use crate::upstream::Live;
+use crate::{
+    data_collection, data_storing, db, lock, opt_out, safety_guard, sqlite_storage, static_api,
+    steam_community,
+};
+use anyhow::{Context, Error, format_err};
+use clap::{Parser, ValueEnum};
+use itertools::Itertools;
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{Instrument, info, info_span};
+
+/// Populate the Distance Database with data from Steam.
+#[derive(Debug, Parser)]
+#[command(version)]
+pub struct Args {
+    /// Only refresh the leaderboards of this game mode. Can be given multiple
+    /// times; defaults to all modes.
+    #[arg(long = "mode", value_enum)]
+    pub modes: Vec<Mode>,
+
+    /// Rewrite every leaderboard, even those whose stored hash matches.
+    #[arg(long)]
+    pub force_rebuild: bool,
+
+    /// Reuse the workshop levels stored in the database instead of querying
+    /// the Steam Workshop.
+    #[arg(long)]
+    pub skip_workshop_query: bool,
+
+    /// Only resolve the names of users that aren't in the database yet,
+    /// keeping the stored names of everyone else.
+    #[arg(long)]
+    pub only_new_names: bool,
+
+    /// Download the leaderboards of all modes at once, instead of one mode
+    /// after another.
+    #[arg(long, env = "PARALLEL_MODES")]
+    pub parallel_modes: bool,
+
+    /// The maximum number of gRPC requests in flight at once, across all
+    /// modes.
+    #[arg(long, env = "GRPC_CONCURRENCY", default_value_t = 4)]
+    pub grpc_concurrency: usize,
+
+    /// The maximum rate of gRPC requests per second. The rate is lowered
+    /// automatically while requests fail or are slow.
+    #[arg(long, env = "GRPC_MAX_REQUESTS_PER_SEC", default_value_t = 50.0)]
+    pub grpc_max_requests_per_sec: f64,
+
+    /// gRPC requests that take longer than this many seconds count as a sign
+    /// of overload, lowering the request rate.
+    #[arg(long, env = "GRPC_SLOW_REQUEST_SECS", default_value_t = 10)]
+    pub grpc_slow_request_secs: u64,
+
+    /// Where to download leaderboards from. `auto` uses the gRPC servers, and
+    /// falls back to Steam Community for leaderboards they fail to return.
+    #[arg(long, env = "LEADERBOARD_SOURCE", value_enum, default_value_t = LeaderboardSourceKind::Auto)]
+    pub leaderboard_source: LeaderboardSourceKind,
+
+    /// Record every response received while collecting data to this
+    /// directory, so the run can be replayed later.
+    #[arg(
+        long,
+        env = "RECORD_FIXTURES",
+        value_name = "DIR",
+        conflicts_with = "replay_fixtures"
+    )]
+    pub record_fixtures: Option<PathBuf>,
+
+    /// Replay the responses recorded to this directory instead of contacting
+    /// Steam or the gRPC servers.
+    #[arg(long, env = "REPLAY_FIXTURES", value_name = "DIR")]
+    pub replay_fixtures: Option<PathBuf>,
+
+    /// Also store the data in the SQLite database at this path, creating it
+    /// if it doesn't exist, for an offline copy of the database.
+    #[arg(long, env = "SQLITE_COPY", value_name = "FILE")]
+    pub sqlite_copy: Option<PathBuf>,
+
+    /// Also export the collected data as Parquet files to this directory.
+    /// Only what the run collected is exported, so runs limited to some modes
+    /// export no entries for the others.
+    #[arg(long, env = "PARQUET_EXPORT", value_name = "DIR")]
+    pub parquet_export: Option<PathBuf>,
+
+    /// Export each mode's leaderboard entries to a partition of their own.
+    #[arg(long, env = "PARQUET_PARTITION_BY_MODE", requires = "parquet_export")]
+    pub parquet_partition_by_mode: bool,
+
+    /// Also generate the static JSON API from the stored data in this
+    /// directory, rewriting only the files that changed.
+    #[arg(long, env = "STATIC_API_DIR", value_name = "DIR")]
+    pub static_api: Option<PathBuf>,
+
+    /// The database to store into.
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    pub database_url: Option<String>,
+
+    /// Refuse to store leaderboards of a mode that hold more than this
+    /// percentage fewer entries than the stored ones. Off unless given.
+    #[arg(long, env = "MAX_ENTRY_DROP_PERCENT", value_name = "PERCENT")]
+    pub max_entry_drop_percent: Option<u64>,
+
+    /// How many seconds to wait for another populator to release the
+    /// database lock before giving up.
+    #[arg(long, env = "LOCK_WAIT_SECS", default_value_t = 0)]
+    pub lock_wait_secs: u64,
+
+    /// The format of log output. Progress bars are only drawn for text logs.
+    #[arg(long, env = "LOG_FORMAT", value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+/// A game mode, as given to `--mode`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Mode {
+    Sprint,
+    Challenge,
+    Stunt,
+}
+
+impl Args {
+    /// Which parts of the data to refresh.
+    pub fn run_options(&self) -> RunOptions {
+        let all_modes = self.modes.is_empty();
+
+        RunOptions {
+            sprint: all_modes || self.modes.contains(&Mode::Sprint),
+            challenge: all_modes || self.modes.contains(&Mode::Challenge),
+            stunt: all_modes || self.modes.contains(&Mode::Stunt),
+            force_rebuild: self.force_rebuild,
+            skip_workshop_query: self.skip_workshop_query,
+            only_new_names: self.only_new_names,
+            parallel_modes: self.parallel_modes,
+        }
+    }
+
+    /// Whether to record or replay fixtures, and where.
+    pub fn fixtures(&self) -> Option<Fixtures> {
+        match (&self.record_fixtures, &self.replay_fixtures) {
+            (_, Some(dir)) => Some(Fixtures::Replay(dir.clone())),
+            (Some(dir), None) => Some(Fixtures::Record(dir.clone())),
+            (None, None) => None,
+        }
+    }
+
+    /// The database to store into, which must be given.
+    pub fn database_url(&self) -> Result<&str, Error> {
+        self.database_url
+            .as_deref()
+            .context("Environment variable DATABASE_URL is not set")
+            .context(FailureKind::Config)
+    }
+
+    /// How hard to hit the gRPC servers.
+    pub fn throttle_config(&self) -> ThrottleConfig {
+        ThrottleConfig {
+            concurrency: self.grpc_concurrency,
+            max_rate: self.grpc_max_requests_per_sec,
+            slow_request: Duration::from_secs(self.grpc_slow_request_secs),
+        }
+    }
+}
+
+/// Runs a population as configured by `args`, recording how it went in
+/// `report`. The environment variables `args` doesn't cover, like
+/// `STEAM_WEB_API_KEY`, are read as the run needs them.
+pub async fn run(args: &Args, report: &mut RunReport) -> Result<(), Error> {
+    let options = args.run_options();
+    let fixtures = args.fixtures();
+    let lock_wait = Duration::from_secs(args.lock_wait_secs);
+
+    // Replaying fixtures needs neither Steam nor the gRPC servers
+    let replay = match &fixtures {
+        Some(Fixtures::Replay(dir)) => Some(
+            Replay::load(dir)
+                .context("error loading fixtures")
+                .context(FailureKind::Config)?,
+        ),
+        _ => None,
+    };
+    let grpc_server_addresses = match replay {
+        Some(_) => Vec::new(),
+        None => grpc_server_addresses()?,
+    };
+
+    info!("Connecting to database");
+    let mut db = db::connect(args.database_url()?).await?;
+    info!("Connected to database");
+    lock::acquire(&db, lock_wait)
+        .instrument(info_span!("lock"))
+        .await?;
+    opt_out::create_table(&db).await?;
+
+    let collection_span = info_span!("data_collection");
+    let mut distance_data = if let Some(replay) = replay {
+        data_collection::run(&replay, &db, options, report)
+            .instrument(collection_span)
+            .await
+            .context("error acquiring data")?
+    } else {
+        let steam_web_api_key = env::var("STEAM_WEB_API_KEY")
+            .context("Environment variable STEAM_WEB_API_KEY is not set")
+            .context(FailureKind::Config)?;
+
+        let web_client = reqwest::Client::new();
+
+        info!(addresses = ?grpc_server_addresses, "Connecting to Distance gRPC servers");
+        let grpc = GrpcPool::connect(grpc_server_addresses, Throttle::new(args.throttle_config()))
+            .await
+            .context(FailureKind::Upstream)?;
+
+        let steam_community_url = env::var("STEAM_COMMUNITY_URL")
+            .unwrap_or_else(|_| steam_community::DEFAULT_BASE_URL.to_owned());
+        let leaderboards = Leaderboards::new(
+            args.leaderboard_source,
+            &grpc,
+            SteamCommunity::new(web_client.clone(), steam_community_url),
+        );
+
+        let live = Live {
+            web_client,
+            web_api_key: steam_web_api_key,
+            grpc: &grpc,
+            leaderboards,
+        };
+
+        if let Some(Fixtures::Record(dir)) = &fixtures {
+            let recorder = Recorder::new(live);
+            let result = data_collection::run(&recorder, &db, options, report)
+                .instrument(collection_span)
+                .await;
+            let saved = recorder.save(dir);
+            let distance_data = result.context("error acquiring data")?;
+            saved.context("error saving fixtures")?;
+            distance_data
+        } else {
+            data_collection::run(&live, &db, options, report)
+                .instrument(collection_span)
+                .await
+                .context("error acquiring data")?
+        }
+    };
+
+    print_stats(&distance_data);
+
+    safety_guard::check(&db, &distance_data, options, args.max_entry_drop_percent)
+        .instrument(info_span!("safety_guard"))
+        .await?;
+
+    // This hides opted-out names in `distance_data` too, for the copies and
+    // exports below
+    data_storing::run(&mut db, &mut distance_data, options, report)
+        .instrument(info_span!("data_storing"))
+        .await
+        .context("error storing data")?;
+
+    if let Some(path) = &args.sqlite_copy {
+        async {
+            let mut sqlite = sqlite_storage::open(path)?;
+            data_storing::store(
+                SqliteStorage::begin(&mut sqlite)?,
+                &distance_data,
+                options,
+                &mut RunReport::new(),
+            )
+            .await
+        }
+        .instrument(info_span!("sqlite_copy"))
+        .await
+        .context("error storing the SQLite copy")?;
+    }
+
+    if let Some(dir) = &args.parquet_export {
+        let options = ParquetOptions {
+            partition_by_mode: args.parquet_partition_by_mode,
+        };
+        info_span!("parquet_export")
+            .in_scope(|| parquet_export::write(&distance_data, dir, options))
+            .context("error exporting Parquet files")?;
+    }
+
+    if let Some(dir) = &args.static_api {
+        static_api::write(&mut db, dir)
+            .instrument(info_span!("static_api"))
+            .await
+            .context("error generating the static API")?;
+    }
+
+    Ok(())
+}
+
+/// Returns the addresses listed in `GRPC_SERVER_ADDRESS`.
+fn grpc_server_addresses() -> Result<Vec<String>, Error> {
+    let addresses = env::var("GRPC_SERVER_ADDRESS")
+        .context("The environment variable `GRPC_SERVER_ADDRESS` must be set.")
+        .context(FailureKind::Config)?
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::to_owned)
+        .collect_vec();
+    if addresses.is_empty() {
+        return Err(
+            format_err!("`GRPC_SERVER_ADDRESS` doesn't contain any addresses")
+                .context(FailureKind::Config),
+        );
+    }
+
+    Ok(addresses)
+}
+
+fn print_stats(data: &DistanceData) {
+    let total_levels = data.levels.len();
+    let official_levels = data
+        .levels
+        .iter()
+        .filter(|level| level.workshop_level_details.is_none())
+        .count();
+    let workshop_levels = total_levels - official_levels;
+    let total_users = data.users.len();
+
+    let sprint_entries: usize = data
+        .levels
+        .iter()
+        .map(|level| level.sprint_entries.len())
+        .sum();
+    let challenge_entries: usize = data
+        .levels
+        .iter()
+        .map(|level| level.challenge_entries.len())
+        .sum();
+    let stunt_entries: usize = data
+        .levels
+        .iter()
+        .map(|level| level.stunt_entries.len())
+        .sum();
+    let total_entries = sprint_entries + challenge_entries + stunt_entries;
+    info!(
+        total_levels,
+        official_levels,
+        workshop_levels,
+        total_users,
+        total_entries,
+        sprint_entries,
+        challenge_entries,
+        stunt_entries,
+        "Collected data"
+    );
+}
@@ -0,0 +1,241 @@
+//! Storing data in the Postgres database `create_db.sql` describes.
+
+use crate::common::{Level, User};
+use crate::storage::{LeaderboardHashes, LeaderboardReplacement, Storage};
+use anyhow::Error;
+use futures::prelude::*;
+use futures::stream::{self, FuturesOrdered};
+use std::fmt;
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::Type as PgType;
+use tokio_postgres::{Client, Transaction};
+use tracing::{Instrument, debug, debug_span};
+
+/// A transaction on the Postgres database. Statements are pipelined, so many
+/// rows are in flight at once.
+pub struct PostgresStorage<'a> {
+    transaction: Transaction<'a>,
+}
+
+impl fmt::Debug for PostgresStorage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresStorage").finish_non_exhaustive()
+    }
+}
+
+impl<'a> PostgresStorage<'a> {
+    /// Starts a transaction on `db`.
+    pub async fn begin(db: &'a mut Client) -> Result<Self, Error> {
+        Ok(PostgresStorage {
+            transaction: db.transaction().await?,
+        })
+    }
+}
+
+impl Storage for PostgresStorage<'_> {
+    async fn upsert_users(&mut self, users: &[User]) -> Result<(), Error> {
+        let transaction = &self.transaction;
+        let stmt = &transaction
+            .prepare("INSERT INTO users VALUES ($1, $2) ON CONFLICT (steam_id) DO UPDATE SET name = EXCLUDED.name")
+            .await?;
+        stream::iter(users)
+            .map(Ok)
+            .try_for_each_concurrent(None, |user| async move {
+                transaction
+                    .execute(stmt, &[&(user.steam_id as i64), &user.name])
+                    .map_ok(drop)
+                    .await
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn upsert_levels(&mut self, levels: &[Level]) -> Result<(), Error> {
+        let transaction = &self.transaction;
+        let stmt = &transaction
+            .prepare("INSERT INTO levels (id, name, is_sprint, is_challenge, is_stunt) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, is_sprint = EXCLUDED.is_sprint, is_challenge = EXCLUDED.is_challenge, is_stunt = EXCLUDED.is_stunt")
+            .await?;
+        stream::iter(levels)
+            .map(Ok)
+            .try_for_each_concurrent(None, |level| async move {
+                transaction
+                    .execute(
+                        stmt,
+                        &[
+                            &level.id,
+                            &level.name,
+                            &level.is_sprint,
+                            &level.is_challenge,
+                            &level.is_stunt,
+                        ],
+                    )
+                    .map_ok(drop)
+                    .await
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn upsert_workshop_details(&mut self, levels: &[Level]) -> Result<(), Error> {
+        let transaction = &self.transaction;
+        let stmt = &transaction
+            .prepare("INSERT INTO workshop_level_details VALUES ($1, $2, $3) ON CONFLICT (level_id) DO UPDATE SET raw_details = EXCLUDED.raw_details, tags = EXCLUDED.tags")
+            .await?;
+        stream::iter(levels)
+            .filter_map(|level| {
+                future::ready(
+                    level
+                        .workshop_level_details
+                        .as_ref()
+                        .map(|details| Ok((level.id, details))),
+                )
+            })
+            .try_for_each_concurrent(None, |(level_id, (details, json))| async move {
+                transaction
+                    .execute(
+                        stmt,
+                        &[
+                            &level_id,
+                            json,
+                            &details.tags.iter().map(|tag| &tag.tag).collect::<Vec<_>>(),
+                        ],
+                    )
+                    .map_ok(drop)
+                    .await
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    async fn leaderboard_hashes(
+        &mut self,
+        level_ids: &[i64],
+    ) -> Result<Vec<LeaderboardHashes>, Error> {
+        let transaction = &self.transaction;
+        let stmt = &transaction
+            .prepare("SELECT sprint_leaderboard_hash, challenge_leaderboard_hash, stunt_leaderboard_hash FROM levels WHERE id = $1")
+            .await?;
+        let hashes = level_ids
+            .iter()
+            .map(|level_id| async move {
+                let row = transaction.query_one(stmt, &[level_id]).await?;
+
+                Ok::<_, Error>(LeaderboardHashes {
+                    sprint: row.get(0),
+                    challenge: row.get(1),
+                    stunt: row.get(2),
+                })
+            })
+            .collect::<FuturesOrdered<_>>()
+            .try_collect()
+            .await?;
+
+        Ok(hashes)
+    }
+
+    async fn replace_leaderboards(
+        &mut self,
+        replacements: &[LeaderboardReplacement<'_>],
+    ) -> Result<(), Error> {
+        let transaction = &self.transaction;
+        stream::iter(replacements)
+            .map(Ok)
+            .try_for_each_concurrent(None, |replacement| {
+                let level_id = replacement.level_id;
+                let mode = replacement.entries.mode();
+                async move {
+                    debug!(
+                        mode,
+                        entries = replacement.entries.len(),
+                        "Rewriting leaderboard"
+                    );
+
+                    let table = format!("{mode}_leaderboard_entries");
+                    transaction
+                        .execute(
+                            &format!("DELETE FROM {table} WHERE level_id = $1"),
+                            &[&level_id],
+                        )
+                        .await?;
+
+                    if !replacement.entries.is_empty() {
+                        async {
+                            let sink = transaction
+                                .copy_in(&format!("COPY {table} FROM STDIN WITH (FORMAT binary)"))
+                                .await?;
+                            let mut writer = Box::pin(BinaryCopyInWriter::new(
+                                sink,
+                                &[
+                                    PgType::INT8,
+                                    PgType::INT8,
+                                    PgType::INT4,
+                                    PgType::INT4,
+                                    PgType::BOOL,
+                                ],
+                            ));
+                            for (steam_id, value, rank, has_replay) in replacement.entries.rows() {
+                                writer
+                                    .as_mut()
+                                    .write(&[&level_id, &steam_id, &value, &rank, &has_replay])
+                                    .await?;
+                            }
+                            writer.as_mut().finish().await?;
+
+                            Ok::<_, tokio_postgres::Error>(())
+                        }
+                        .instrument(debug_span!("copy", table, rows = replacement.entries.len()))
+                        .await?;
+                    }
+
+                    transaction
+                        .execute(
+                            &format!(
+                                "UPDATE levels SET {mode}_leaderboard_hash = $2 WHERE id = $1"
+                            ),
+                            &[&level_id, &replacement.hash],
+                        )
+                        .await?;
+
+                    Ok::<_, Error>(())
+                }
+                .instrument(debug_span!("store_level_entries", level_id, mode))
+            })
+            .await
+    }
+
+    async fn mark_updated(&mut self) -> Result<(), Error> {
+        let transaction = &mut self.transaction;
+        let nested_transaction = transaction.transaction().await?;
+        let result = nested_transaction
+            .batch_execute("INSERT INTO metadata (last_updated) VALUES (now())")
+            .await;
+        match result {
+            // No timestamp existed
+            Ok(_) => {
+                nested_transaction.commit().await?;
+            }
+            // Timestamp already existed
+            Err(e) if e.code() == Some(&tokio_postgres::error::SqlState::UNIQUE_VIOLATION) => {
+                nested_transaction.rollback().await?;
+                transaction
+                    .batch_execute("UPDATE metadata SET last_updated = now()")
+                    .await?;
+            }
+            // Some other error
+            Err(e) => {
+                return Err(e.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn commit(self) -> Result<(), Error> {
+        self.transaction.commit().await?;
+
+        Ok(())
+    }
+}
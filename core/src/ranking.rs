@@ -0,0 +1,21 @@
+//! How leaderboard positions are turned into ranks.
+
+use crate::leaderboard_source::LeaderboardEntry;
+
+/// Pairs each of `entries`, ordered best first, with its rank.
+///
+/// Ranks are "standard competition" ranks: an entry's rank is one more than
+/// the number of entries ahead of it, so tied entries share a rank, and the
+/// next rank after a tie is skipped (1, 2, 2, 4).
+pub fn rank(entries: Vec<LeaderboardEntry>) -> Vec<(LeaderboardEntry, u32)> {
+    let mut ranked: Vec<(LeaderboardEntry, u32)> = Vec::with_capacity(entries.len());
+    for (entry, position) in entries.into_iter().zip(1..) {
+        let rank = match ranked.last() {
+            Some((previous, previous_rank)) if previous.score == entry.score => *previous_rank,
+            _ => position,
+        };
+        ranked.push((entry, rank));
+    }
+
+    ranked
+}
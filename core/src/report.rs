@@ -0,0 +1,142 @@
+//! The summary of a run that the manager reads.
+
+use crate::failure::{EXIT_FAILURE, EXIT_PARTIAL, FailureKind};
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How a populator run ended, along with per-phase statistics.
+///
+/// If the `POPULATOR_REPORT_PATH` environment variable is set, the report is
+/// written there as JSON at the end of the run, whether it succeeded or not,
+/// so the manager can pick it up with [`RunReport::read`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunReport {
+    pub status: RunStatus,
+    pub exit_code: u8,
+    pub failure: Option<FailureKind>,
+    pub error: Option<String>,
+    /// The number of leaderboards per mode that couldn't be downloaded, and
+    /// were left as they were.
+    pub skipped_leaderboards: BTreeMap<String, u64>,
+    pub phases: Vec<PhaseReport>,
+}
+
+/// How a run ended.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    #[default]
+    Succeeded,
+    /// Everything was stored, except some skipped leaderboards.
+    Partial,
+    Failed,
+}
+
+/// How long a phase of the run took, and how many items it handled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseReport {
+    pub name: String,
+    pub duration_secs: f64,
+    pub items: u64,
+}
+
+impl RunReport {
+    /// Returns the report of a run that hasn't done anything yet.
+    pub fn new() -> Self {
+        RunReport::default()
+    }
+
+    /// Starts timing the phase `name`, to be recorded with
+    /// [`PhaseTimer::finish`].
+    pub fn start_phase(&self, name: &'static str) -> PhaseTimer {
+        PhaseTimer {
+            name,
+            start: Instant::now(),
+        }
+    }
+
+    /// Records that the phase `name` took `duration` and handled `items`.
+    pub fn record(&mut self, name: &'static str, duration: Duration, items: usize) {
+        self.phases.push(PhaseReport {
+            name: name.to_owned(),
+            duration_secs: duration.as_secs_f64(),
+            items: items as u64,
+        });
+    }
+
+    /// Records how many of `mode`'s leaderboards were skipped.
+    pub fn record_skipped_leaderboards(&mut self, mode: &'static str, count: usize) {
+        self.skipped_leaderboards
+            .insert(mode.to_owned(), count as u64);
+    }
+
+    /// Records how the run ended, returning the exit code for it.
+    pub fn finish(&mut self, result: &Result<(), Error>) -> u8 {
+        match result {
+            Ok(()) if self.skipped_leaderboards.values().any(|&n| n > 0) => {
+                self.status = RunStatus::Partial;
+                self.exit_code = EXIT_PARTIAL;
+            }
+            Ok(()) => {
+                self.status = RunStatus::Succeeded;
+                self.exit_code = 0;
+            }
+            Err(e) => {
+                let kind = FailureKind::of(e);
+                self.status = RunStatus::Failed;
+                self.exit_code = kind.map_or(EXIT_FAILURE, FailureKind::exit_code);
+                self.failure = kind;
+                self.error = Some(format!("{e:#}"));
+            }
+        }
+
+        self.exit_code
+    }
+
+    /// Writes the report to `POPULATOR_REPORT_PATH`, if it's set.
+    pub fn write_if_requested(&self) -> Result<(), Error> {
+        match env::var_os("POPULATOR_REPORT_PATH") {
+            Some(path) => self.write(Path::new(&path)),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads a report written with [`RunReport::write`] from `path`.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let json = fs::read(path)
+            .with_context(|| format!("couldn't read run report from {}", path.display()))?;
+        let report = serde_json::from_slice(&json)
+            .with_context(|| format!("invalid run report at {}", path.display()))?;
+
+        Ok(report)
+    }
+
+    /// Writes the report to `path` as JSON.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_vec_pretty(self)?;
+        fs::write(path, json)
+            .with_context(|| format!("couldn't write run report to {}", path.display()))?;
+
+        Ok(())
+    }
+}
+
+/// Times a phase of the run.
+#[derive(Debug)]
+pub struct PhaseTimer {
+    name: &'static str,
+    start: Instant,
+}
+
+impl PhaseTimer {
+    /// Records the phase in `report`, as having handled `items`.
+    pub fn finish(self, report: &mut RunReport, items: usize) {
+        report.record(self.name, self.start.elapsed(), items);
+    }
+}
@@ -0,0 +1,75 @@
+//! Refusing to store collected data that looks broken.
+
+use crate::common::{DistanceData, Level, RunOptions};
+use crate::failure::FailureKind;
+use anyhow::{Error, format_err};
+use tracing::debug;
+
+/// Returns the number of downloaded entries of a level, if the level has a
+/// leaderboard for the mode that wasn't skipped.
+type DownloadedEntries = fn(&Level) -> Option<usize>;
+
+/// Checks the collected data before it is stored, if `max_drop_percent` is
+/// given.
+///
+/// Fails if the downloaded leaderboards of a mode hold more than
+/// `max_drop_percent` percent fewer entries in total than the same
+/// leaderboards in the database, since that's more likely a problem upstream
+/// than players vanishing. The check is off if it's `None` or 100.
+pub async fn check(
+    db: &tokio_postgres::Client,
+    data: &DistanceData,
+    options: RunOptions,
+    max_drop_percent: Option<u64>,
+) -> Result<(), Error> {
+    let Some(max_drop_percent) = max_drop_percent.filter(|&x| x < 100) else {
+        return Ok(());
+    };
+
+    let modes: [(&str, bool, DownloadedEntries); 3] = [
+        ("sprint", options.sprint, |l| {
+            (l.is_sprint && !l.sprint_skipped).then_some(l.sprint_entries.len())
+        }),
+        ("challenge", options.challenge, |l| {
+            (l.is_challenge && !l.challenge_skipped).then_some(l.challenge_entries.len())
+        }),
+        ("stunt", options.stunt, |l| {
+            (l.is_stunt && !l.stunt_skipped).then_some(l.stunt_entries.len())
+        }),
+    ];
+
+    for (mode, enabled, downloaded_entries) in modes {
+        if !enabled {
+            continue;
+        }
+
+        let mut level_ids = Vec::new();
+        let mut downloaded = 0;
+        for level in &data.levels {
+            if let Some(n) = downloaded_entries(level) {
+                level_ids.push(level.id);
+                downloaded += n as u64;
+            }
+        }
+
+        let row = db
+            .query_one(
+                &format!(
+                    "SELECT count(*) FROM {mode}_leaderboard_entries WHERE level_id = ANY($1)"
+                ),
+                &[&level_ids],
+            )
+            .await?;
+        let stored = row.get::<_, i64>(0) as u64;
+        debug!(mode, downloaded, stored, "Compared leaderboard sizes");
+
+        if downloaded * 100 < stored * (100 - max_drop_percent) {
+            return Err(format_err!(
+                "downloaded {downloaded} {mode} leaderboard entries, but the database holds {stored} for the same levels"
+            )
+            .context(FailureKind::SafetyGuard));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Storing data in a single-file SQLite copy of the database, with the schema
+//! of `create_db_sqlite.sql`.
+
+use crate::common::{Level, User};
+use crate::storage::{LeaderboardHashes, LeaderboardReplacement, Storage};
+use anyhow::{Context, Error};
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use std::path::Path;
+use tracing::{debug, debug_span};
+
+/// Opens the SQLite database at `path`, creating it with the tables and views
+/// of `create_db_sqlite.sql` if it doesn't exist yet.
+pub fn open(path: &Path) -> Result<Connection, Error> {
+    let db = Connection::open(path)
+        .with_context(|| format!("error opening SQLite database {}", path.display()))?;
+    db.pragma_update(None, "foreign_keys", true)?;
+
+    let has_schema = db
+        .query_row(
+            "SELECT 1 FROM sqlite_schema WHERE type = 'table' AND name = 'levels'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !has_schema {
+        db.execute_batch(include_str!("../../create_db_sqlite.sql"))
+            .context("error creating the SQLite schema")?;
+    }
+
+    Ok(db)
+}
+
+/// A transaction on a SQLite database opened with [`open`].
+#[derive(Debug)]
+pub struct SqliteStorage<'a> {
+    transaction: Transaction<'a>,
+}
+
+impl<'a> SqliteStorage<'a> {
+    /// Starts a transaction on `db`.
+    pub fn begin(db: &'a mut Connection) -> Result<Self, Error> {
+        Ok(SqliteStorage {
+            transaction: db.transaction()?,
+        })
+    }
+}
+
+impl Storage for SqliteStorage<'_> {
+    async fn upsert_users(&mut self, users: &[User]) -> Result<(), Error> {
+        let mut stmt = self.transaction.prepare(
+            "INSERT INTO users VALUES (?1, ?2) ON CONFLICT (steam_id) DO UPDATE SET name = excluded.name",
+        )?;
+        for user in users {
+            stmt.execute(params![user.steam_id as i64, user.name])?;
+        }
+
+        Ok(())
+    }
+
+    async fn upsert_levels(&mut self, levels: &[Level]) -> Result<(), Error> {
+        let mut stmt = self.transaction.prepare("INSERT INTO levels (id, name, is_sprint, is_challenge, is_stunt) VALUES (?1, ?2, ?3, ?4, ?5) ON CONFLICT (id) DO UPDATE SET name = excluded.name, is_sprint = excluded.is_sprint, is_challenge = excluded.is_challenge, is_stunt = excluded.is_stunt")?;
+        for level in levels {
+            stmt.execute(params![
+                level.id,
+                level.name,
+                level.is_sprint,
+                level.is_challenge,
+                level.is_stunt
+            ])?;
+        }
+
+        Ok(())
+    }
+
+    async fn upsert_workshop_details(&mut self, levels: &[Level]) -> Result<(), Error> {
+        let mut stmt = self.transaction.prepare("INSERT INTO workshop_level_details VALUES (?1, ?2, ?3) ON CONFLICT (level_id) DO UPDATE SET raw_details = excluded.raw_details, tags = excluded.tags")?;
+        for level in levels {
+            let Some((details, json)) = &level.workshop_level_details else {
+                continue;
+            };
+            let tags: Vec<_> = details.tags.iter().map(|tag| &tag.tag).collect();
+            stmt.execute(params![
+                level.id,
+                json.to_string(),
+                serde_json::to_string(&tags)?
+            ])?;
+        }
+
+        Ok(())
+    }
+
+    async fn leaderboard_hashes(
+        &mut self,
+        level_ids: &[i64],
+    ) -> Result<Vec<LeaderboardHashes>, Error> {
+        let mut stmt = self.transaction.prepare("SELECT sprint_leaderboard_hash, challenge_leaderboard_hash, stunt_leaderboard_hash FROM levels WHERE id = ?1")?;
+        let hashes = level_ids
+            .iter()
+            .map(|level_id| {
+                stmt.query_row([level_id], |row| {
+                    Ok(LeaderboardHashes {
+                        sprint: row.get(0)?,
+                        challenge: row.get(1)?,
+                        stunt: row.get(2)?,
+                    })
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(hashes)
+    }
+
+    async fn replace_leaderboards(
+        &mut self,
+        replacements: &[LeaderboardReplacement<'_>],
+    ) -> Result<(), Error> {
+        for replacement in replacements {
+            let level_id = replacement.level_id;
+            let mode = replacement.entries.mode();
+            let _span = debug_span!("store_level_entries", level_id, mode).entered();
+            debug!(
+                mode,
+                entries = replacement.entries.len(),
+                "Rewriting leaderboard"
+            );
+
+            let table = format!("{mode}_leaderboard_entries");
+            self.transaction.execute(
+                &format!("DELETE FROM {table} WHERE level_id = ?1"),
+                [level_id],
+            )?;
+            let mut stmt = self
+                .transaction
+                .prepare_cached(&format!("INSERT INTO {table} VALUES (?1, ?2, ?3, ?4, ?5)"))?;
+            for (steam_id, value, rank, has_replay) in replacement.entries.rows() {
+                stmt.execute(params![level_id, steam_id, value, rank, has_replay])?;
+            }
+            self.transaction.execute(
+                &format!("UPDATE levels SET {mode}_leaderboard_hash = ?2 WHERE id = ?1"),
+                params![level_id, replacement.hash],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    async fn mark_updated(&mut self) -> Result<(), Error> {
+        self.transaction.execute(
+            "INSERT INTO metadata (last_updated) VALUES (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')) ON CONFLICT (onerow_id) DO UPDATE SET last_updated = excluded.last_updated",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    async fn commit(self) -> Result<(), Error> {
+        self.transaction.commit()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+//! Generating a static JSON API from the stored data, to host leaderboards on
+//! a CDN without a query server.
+//!
+//! The API is a directory tree of JSON files:
+//!
+//! - `levels.json`: every level with its leaderboards, and when the database
+//!   was last updated
+//! - `levels/<id>/<mode>.json`: a level's leaderboard in a mode, with the
+//!   players' names
+//! - `players/<steam_id>.json`: a player's name, and their entries with ranks
+//! - `wr_changes.json`: the most recent world record changes, newest first
+//!
+//! Steam IDs are written as strings, since they don't fit in a JavaScript
+//! number. A file is only rewritten when its contents change, so syncing the
+//! tree to a host only uploads what changed.
+//!
+//! World record changes are found by comparing each leaderboard with the file
+//! written for it before, so the API should be generated after every run to
+//! catch them all.
+
+use crate::common::DistanceData;
+use crate::db;
+use crate::storage::LeaderboardEntries;
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio_postgres::Client;
+use tracing::info;
+
+/// The number of world record changes `wr_changes.json` keeps.
+pub const RECENT_WR_CHANGES: usize = 100;
+
+/// What a generation did.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Summary {
+    /// Files that were created, or whose contents changed.
+    pub written: usize,
+    /// Files left alone, since their contents didn't change.
+    pub unchanged: usize,
+    /// World record changes found.
+    pub wr_changes: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct LevelIndex<'a> {
+    last_updated: Option<&'a str>,
+    levels: Vec<LevelSummary<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct LevelSummary<'a> {
+    id: i64,
+    name: &'a str,
+    is_workshop: bool,
+    leaderboards: Vec<LeaderboardSummary>,
+}
+
+#[derive(Debug, Serialize)]
+struct LeaderboardSummary {
+    mode: &'static str,
+    entries: usize,
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Leaderboard<'a> {
+    level_id: i64,
+    level_name: &'a str,
+    mode: &'static str,
+    entries: Vec<Entry<'a>>,
+}
+
+/// An entry of a leaderboard file. Sprint and challenge entries have a `time`
+/// in milliseconds, and stunt entries a `score`.
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    rank: i32,
+    steam_id: String,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<i32>,
+    has_replay: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Player<'a> {
+    steam_id: String,
+    name: &'a str,
+    entries: Vec<PlayerEntry<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct PlayerEntry<'a> {
+    level_id: i64,
+    level_name: &'a str,
+    mode: &'static str,
+    rank: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<i32>,
+    has_replay: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WrChanges {
+    changes: Vec<WrChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WrChange {
+    level_id: i64,
+    level_name: String,
+    mode: String,
+    /// When the database was last updated as of the generation that found
+    /// the change.
+    detected_at: Option<String>,
+    record: WorldRecord,
+    previous: WorldRecord,
+}
+
+/// The first place of a leaderboard, as read back from its file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WorldRecord {
+    steam_id: String,
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    time: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score: Option<i32>,
+}
+
+impl WorldRecord {
+    /// Whether `other` is the same record by the same player, regardless of
+    /// their name.
+    fn is_same(&self, other: &WorldRecord) -> bool {
+        (&self.steam_id, self.time, self.score) == (&other.steam_id, other.time, other.score)
+    }
+}
+
+/// Generates the API in `dir` from what's stored in `db`, creating `dir` if
+/// needed.
+pub async fn write(db: &mut Client, dir: &Path) -> Result<Summary, Error> {
+    let data = db::load(db).await?;
+    let last_updated: Option<String> = db
+        .query_opt(
+            "SELECT to_char(last_updated AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') FROM metadata",
+            &[],
+        )
+        .await?
+        .and_then(|row| row.get(0));
+
+    generate(&data, last_updated.as_deref(), dir)
+}
+
+/// Generates the API in `dir` from `data`, which was last updated at
+/// `last_updated`, creating `dir` if needed.
+pub fn generate(
+    data: &DistanceData,
+    last_updated: Option<&str>,
+    dir: &Path,
+) -> Result<Summary, Error> {
+    info!(dir = %dir.display(), "Generating the static API");
+    let mut summary = Summary::default();
+    let names: HashMap<u64, &str> = data
+        .users
+        .iter()
+        .map(|user| (user.steam_id, user.name.as_str()))
+        .collect();
+    let name = |steam_id: i64| names.get(&(steam_id as u64)).copied().unwrap_or_default();
+
+    let mut levels = Vec::with_capacity(data.levels.len());
+    let mut players = HashMap::<u64, Vec<PlayerEntry<'_>>>::new();
+    let mut wr_changes = Vec::new();
+    for level in &data.levels {
+        let has_mode = [level.is_sprint, level.is_challenge, level.is_stunt];
+        let mut leaderboards = Vec::new();
+        for (entries, _) in LeaderboardEntries::of_level(level)
+            .into_iter()
+            .zip(has_mode)
+            .filter(|&(_, has_mode)| has_mode)
+        {
+            let mode = entries.mode();
+            let is_stunt = matches!(entries, LeaderboardEntries::Stunt(_));
+
+            let entries: Vec<_> = entries
+                .rows()
+                .into_iter()
+                .map(|(steam_id, value, rank, has_replay)| {
+                    let (time, score) = if is_stunt {
+                        (None, Some(value))
+                    } else {
+                        (Some(value), None)
+                    };
+                    players
+                        .entry(steam_id as u64)
+                        .or_default()
+                        .push(PlayerEntry {
+                            level_id: level.id,
+                            level_name: &level.name,
+                            mode,
+                            rank,
+                            time,
+                            score,
+                            has_replay,
+                        });
+
+                    Entry {
+                        rank,
+                        steam_id: steam_id.to_string(),
+                        name: name(steam_id),
+                        time,
+                        score,
+                        has_replay,
+                    }
+                })
+                .collect();
+
+            let path = format!("levels/{}/{mode}.json", level.id);
+            let file = dir.join(&path);
+            let existing = read_existing(&file)?;
+            let record = entries.first().map(|entry| WorldRecord {
+                steam_id: entry.steam_id.clone(),
+                name: entry.name.to_owned(),
+                time: entry.time,
+                score: entry.score,
+            });
+            if let (Some(record), Some(previous)) = (record, previous_record(&file, &existing)?)
+                && !record.is_same(&previous)
+            {
+                wr_changes.push(WrChange {
+                    level_id: level.id,
+                    level_name: level.name.clone(),
+                    mode: mode.to_owned(),
+                    detected_at: last_updated.map(str::to_owned),
+                    record,
+                    previous,
+                });
+            }
+
+            leaderboards.push(LeaderboardSummary {
+                mode,
+                entries: entries.len(),
+                path,
+            });
+            let leaderboard = Leaderboard {
+                level_id: level.id,
+                level_name: &level.name,
+                mode,
+                entries,
+            };
+            write_if_changed(&file, &leaderboard, existing, &mut summary)?;
+        }
+
+        levels.push(LevelSummary {
+            id: level.id,
+            name: &level.name,
+            is_workshop: level.workshop_level_details.is_some(),
+            leaderboards,
+        });
+    }
+
+    let index = LevelIndex {
+        last_updated,
+        levels,
+    };
+    let file = dir.join("levels.json");
+    write_if_changed(&file, &index, read_existing(&file)?, &mut summary)?;
+
+    for user in &data.users {
+        let player = Player {
+            steam_id: user.steam_id.to_string(),
+            name: &user.name,
+            entries: players.remove(&user.steam_id).unwrap_or_default(),
+        };
+        let file = dir.join(format!("players/{}.json", user.steam_id));
+        write_if_changed(&file, &player, read_existing(&file)?, &mut summary)?;
+    }
+
+    // The changes found this time go first, followed by the ones kept from
+    // before
+    summary.wr_changes = wr_changes.len();
+    let file = dir.join("wr_changes.json");
+    let existing = read_existing(&file)?;
+    if let Some(contents) = &existing {
+        let kept: WrChanges = serde_json::from_slice(contents)
+            .with_context(|| format!("error parsing {}", file.display()))?;
+        wr_changes.extend(kept.changes);
+    }
+    wr_changes.truncate(RECENT_WR_CHANGES);
+    let changes = WrChanges {
+        changes: wr_changes,
+    };
+    write_if_changed(&file, &changes, existing, &mut summary)?;
+
+    info!(
+        written = summary.written,
+        unchanged = summary.unchanged,
+        wr_changes = summary.wr_changes,
+        "Generated the static API"
+    );
+
+    Ok(summary)
+}
+
+/// Returns the first place of the leaderboard file `existing`, read from
+/// `file`, if there was a file with any entries.
+fn previous_record(file: &Path, existing: &Option<Vec<u8>>) -> Result<Option<WorldRecord>, Error> {
+    let Some(contents) = existing else {
+        return Ok(None);
+    };
+    let mut leaderboard: JsonValue = serde_json::from_slice(contents)
+        .with_context(|| format!("error parsing {}", file.display()))?;
+
+    match leaderboard["entries"].get_mut(0) {
+        Some(entry) => Ok(Some(
+            serde_json::from_value(entry.take())
+                .with_context(|| format!("error parsing {}", file.display()))?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Returns the contents of `path`, if it exists.
+fn read_existing(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::new(e).context(format!("error reading {}", path.display()))),
+    }
+}
+
+/// Writes `value` as JSON to `path`, through a temporary file, unless the
+/// `existing` contents of `path` are the same already.
+fn write_if_changed(
+    path: &Path,
+    value: &impl Serialize,
+    existing: Option<Vec<u8>>,
+    summary: &mut Summary,
+) -> Result<(), Error> {
+    let contents = serde_json::to_vec(value)?;
+    if existing.is_some_and(|existing| existing == contents) {
+        summary.unchanged += 1;
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("error creating directory {}", parent.display()))?;
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    fs::write(&temp_path, contents)
+        .with_context(|| format!("error writing {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).with_context(|| format!("error writing {}", path.display()))?;
+    summary.written += 1;
+
+    Ok(())
+}
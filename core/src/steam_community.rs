@@ -0,0 +1,321 @@
+//! Steam Community's public XML leaderboards.
+
+use crate::leaderboard_source::{LeaderboardEntry, LeaderboardSource};
+use anyhow::{Context, Error, format_err};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tokio::sync::OnceCell;
+use tracing::{debug, info};
+
+const DISTANCE_APP_ID: u32 = 233610;
+
+/// Where Steam Community is, unless `STEAM_COMMUNITY_URL` says otherwise.
+pub const DEFAULT_BASE_URL: &str = "https://steamcommunity.com";
+
+/// The most entries Steam returns per request.
+const PAGE_SIZE: usize = 5000;
+
+/// Steam Community's public XML leaderboards, which can be read without a
+/// Steam client.
+#[derive(Debug)]
+pub struct SteamCommunity {
+    client: reqwest::Client,
+    base_url: String,
+    page_size: usize,
+    /// Leaderboard IDs by name, listed when first needed.
+    leaderboard_ids: OnceCell<HashMap<String, u32>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LeaderboardList {
+    #[serde(rename = "leaderboard", default)]
+    leaderboards: Vec<LeaderboardInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LeaderboardInfo {
+    lbid: u32,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EntriesPage {
+    #[serde(rename = "totalLeaderboardEntries")]
+    total: usize,
+    #[serde(default)]
+    entries: Entries,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Entries {
+    #[serde(rename = "entry", default)]
+    entries: Vec<XmlEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct XmlEntry {
+    steamid: u64,
+    score: i32,
+    /// The UGC handle of the entry's replay; `-1` or `18446744073709551615`
+    /// if there is none.
+    ugcid: String,
+}
+
+impl SteamCommunity {
+    /// Creates a source that downloads from the Steam Community instance at
+    /// `base_url`.
+    pub fn new(client: reqwest::Client, base_url: impl Into<String>) -> Self {
+        SteamCommunity {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            page_size: PAGE_SIZE,
+            leaderboard_ids: OnceCell::new(),
+        }
+    }
+
+    async fn leaderboard_id(&self, leaderboard_name: &str) -> Result<u32, Error> {
+        let ids = self
+            .leaderboard_ids
+            .get_or_try_init(|| self.list_leaderboards())
+            .await?;
+
+        ids.get(leaderboard_name).copied().ok_or_else(|| {
+            format_err!("Steam Community has no leaderboard named {leaderboard_name:?}")
+        })
+    }
+
+    async fn list_leaderboards(&self) -> Result<HashMap<String, u32>, Error> {
+        info!("Listing Steam Community leaderboards");
+        let url = format!(
+            "{}/stats/{DISTANCE_APP_ID}/leaderboards/?xml=1",
+            self.base_url
+        );
+        let list: LeaderboardList = self
+            .get_xml(&url)
+            .await
+            .context("error listing Steam Community leaderboards")?;
+        debug!(
+            leaderboards = list.leaderboards.len(),
+            "Listed Steam Community leaderboards"
+        );
+
+        Ok(list
+            .leaderboards
+            .into_iter()
+            .map(|info| (info.name, info.lbid))
+            .collect())
+    }
+
+    async fn get_xml<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T, Error> {
+        let text = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        quick_xml::de::from_str(&text).with_context(|| format!("invalid XML from {url}"))
+    }
+}
+
+impl LeaderboardSource for SteamCommunity {
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        let id = self.leaderboard_id(leaderboard_name).await?;
+
+        let mut entries = Vec::new();
+        loop {
+            // Entries are numbered from 1, and `end` is inclusive
+            let start = entries.len() + 1;
+            let url = format!(
+                "{}/stats/{DISTANCE_APP_ID}/leaderboards/{id}/?xml=1&start={start}&end={}",
+                self.base_url,
+                start + self.page_size - 1
+            );
+            let page: EntriesPage = self
+                .get_xml(&url)
+                .await
+                .with_context(|| format!("error downloading leaderboard {leaderboard_name:?}"))?;
+
+            let page_len = page.entries.entries.len();
+            entries.extend(
+                page.entries
+                    .entries
+                    .into_iter()
+                    .map(|entry| LeaderboardEntry {
+                        steam_id: entry.steamid,
+                        score: entry.score,
+                        has_replay: !matches!(
+                            entry.ugcid.as_str(),
+                            "-1" | "0" | "18446744073709551615"
+                        ),
+                    }),
+            );
+            if page_len == 0 || entries.len() >= page.total {
+                break;
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const LIST: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<response>
+<appID>233610</appID>
+<leaderboardCount>2</leaderboardCount>
+<leaderboard>
+	<url><![CDATA[https://steamcommunity.com/stats/233610/leaderboards/11/?xml=1]]></url>
+	<lbid>11</lbid>
+	<name><![CDATA[Broken Symmetry_1_stable]]></name>
+	<display_name><![CDATA[Broken Symmetry_1_stable]]></display_name>
+	<entries>3</entries>
+	<sortmethod>1</sortmethod>
+	<displaytype>3</displaytype>
+</leaderboard>
+<leaderboard>
+	<url><![CDATA[https://steamcommunity.com/stats/233610/leaderboards/12/?xml=1]]></url>
+	<lbid>12</lbid>
+	<name><![CDATA[Empty_1_stable]]></name>
+	<display_name><![CDATA[Empty_1_stable]]></display_name>
+	<entries>0</entries>
+	<sortmethod>1</sortmethod>
+	<displaytype>3</displaytype>
+</leaderboard>
+</response>"#;
+
+    fn entries_page(total: usize, entries: &[(u64, i32, &str)]) -> String {
+        let result_count = entries.len();
+        let entries: String = entries
+            .iter()
+            .zip(1..)
+            .map(|((steam_id, score, ugcid), rank)| {
+                format!(
+                    "<entry><steamid>{steam_id}</steamid><score>{score}</score><rank>{rank}</rank><ugcid>{ugcid}</ugcid><details><![CDATA[]]></details></entry>"
+                )
+            })
+            .collect();
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<response>
+<appID>233610</appID>
+<leaderboardID>11</leaderboardID>
+<totalLeaderboardEntries>{total}</totalLeaderboardEntries>
+<resultCount>{result_count}</resultCount>
+<entries>{entries}</entries>
+</response>"#
+        )
+    }
+
+    async fn mock_xml(server: &MockServer, url_path: &str, start: &str, body: String) {
+        Mock::given(method("GET"))
+            .and(path(url_path))
+            .and(query_param("start", start))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(server)
+            .await;
+    }
+
+    async fn server() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/stats/233610/leaderboards/"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(LIST))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        server
+    }
+
+    #[tokio::test]
+    async fn pages_through_entries() {
+        let server = server().await;
+        mock_xml(
+            &server,
+            "/stats/233610/leaderboards/11/",
+            "1",
+            entries_page(3, &[(1, 100, "-1"), (2, 200, "1234")]),
+        )
+        .await;
+        mock_xml(
+            &server,
+            "/stats/233610/leaderboards/11/",
+            "3",
+            entries_page(3, &[(3, 300, "18446744073709551615")]),
+        )
+        .await;
+
+        let mut community = SteamCommunity::new(reqwest::Client::new(), server.uri());
+        community.page_size = 2;
+        let entries = community
+            .leaderboard_entries_all("Broken Symmetry_1_stable")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                LeaderboardEntry {
+                    steam_id: 1,
+                    score: 100,
+                    has_replay: false
+                },
+                LeaderboardEntry {
+                    steam_id: 2,
+                    score: 200,
+                    has_replay: true
+                },
+                LeaderboardEntry {
+                    steam_id: 3,
+                    score: 300,
+                    has_replay: false
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn lists_leaderboards_once() {
+        let server = server().await;
+        mock_xml(
+            &server,
+            "/stats/233610/leaderboards/12/",
+            "1",
+            entries_page(0, &[]),
+        )
+        .await;
+
+        let community = SteamCommunity::new(reqwest::Client::new(), server.uri());
+        for _ in 0..2 {
+            let entries = community
+                .leaderboard_entries_all("Empty_1_stable")
+                .await
+                .unwrap();
+            assert!(entries.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_leaderboard() {
+        let server = server().await;
+
+        let community = SteamCommunity::new(reqwest::Client::new(), server.uri());
+        let error = community
+            .leaderboard_entries_all("Missing_1_stable")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Missing_1_stable"), "{error}");
+    }
+}
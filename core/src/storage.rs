@@ -0,0 +1,119 @@
+//! What the storing phase needs from a database, so the same data can be
+//! stored in Postgres or in a single-file SQLite copy.
+
+use crate::common::{Level, ScoreLeaderboardEntry, TimeLeaderboardEntry, User};
+use anyhow::Error;
+
+/// A database collected data can be stored in, within a single transaction
+/// that [`commit`](Storage::commit) ends.
+pub trait Storage {
+    /// Inserts `users`, or updates the names of those already stored.
+    fn upsert_users(&mut self, users: &[User]) -> impl Future<Output = Result<(), Error>>;
+
+    /// Inserts `levels`, or updates the names and modes of those already
+    /// stored. Their leaderboards are left alone.
+    fn upsert_levels(&mut self, levels: &[Level]) -> impl Future<Output = Result<(), Error>>;
+
+    /// Inserts or updates the workshop details of those of `levels` that have
+    /// them.
+    fn upsert_workshop_details(
+        &mut self,
+        levels: &[Level],
+    ) -> impl Future<Output = Result<(), Error>>;
+
+    /// Returns the stored leaderboard hashes of each of `level_ids`, which
+    /// must all be stored.
+    fn leaderboard_hashes(
+        &mut self,
+        level_ids: &[i64],
+    ) -> impl Future<Output = Result<Vec<LeaderboardHashes>, Error>>;
+
+    /// Replaces the entries of each leaderboard in `replacements`, and stores
+    /// its new hash.
+    fn replace_leaderboards(
+        &mut self,
+        replacements: &[LeaderboardReplacement<'_>],
+    ) -> impl Future<Output = Result<(), Error>>;
+
+    /// Sets the time the database was last updated to now.
+    fn mark_updated(&mut self) -> impl Future<Output = Result<(), Error>>;
+
+    /// Commits everything stored so far.
+    fn commit(self) -> impl Future<Output = Result<(), Error>>;
+}
+
+/// The hashes of a level's stored leaderboards, if they've been stored.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LeaderboardHashes {
+    pub sprint: Option<i64>,
+    pub challenge: Option<i64>,
+    pub stunt: Option<i64>,
+}
+
+/// A leaderboard whose stored entries are to be replaced.
+#[derive(Debug, Copy, Clone)]
+pub struct LeaderboardReplacement<'a> {
+    pub level_id: i64,
+    pub entries: LeaderboardEntries<'a>,
+    /// The hash of `entries`, stored to detect when they change.
+    pub hash: i64,
+}
+
+/// The new entries of a leaderboard, by mode.
+#[derive(Debug, Copy, Clone)]
+pub enum LeaderboardEntries<'a> {
+    Sprint(&'a [TimeLeaderboardEntry]),
+    Challenge(&'a [TimeLeaderboardEntry]),
+    Stunt(&'a [ScoreLeaderboardEntry]),
+}
+
+impl<'a> LeaderboardEntries<'a> {
+    /// The entries of each of `level`'s leaderboards: sprint, challenge and
+    /// stunt.
+    pub fn of_level(level: &'a Level) -> [Self; 3] {
+        [
+            LeaderboardEntries::Sprint(&level.sprint_entries),
+            LeaderboardEntries::Challenge(&level.challenge_entries),
+            LeaderboardEntries::Stunt(&level.stunt_entries),
+        ]
+    }
+
+    /// The name of the mode, as used in table and column names.
+    pub fn mode(&self) -> &'static str {
+        match self {
+            LeaderboardEntries::Sprint(_) => "sprint",
+            LeaderboardEntries::Challenge(_) => "challenge",
+            LeaderboardEntries::Stunt(_) => "stunt",
+        }
+    }
+
+    /// The number of entries.
+    pub fn len(&self) -> usize {
+        match self {
+            LeaderboardEntries::Sprint(entries) | LeaderboardEntries::Challenge(entries) => {
+                entries.len()
+            }
+            LeaderboardEntries::Stunt(entries) => entries.len(),
+        }
+    }
+
+    /// Whether the leaderboard has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The entries as `(steam_id, time or score, rank, has_replay)` rows, in
+    /// the order of the entry tables' columns after `level_id`.
+    pub fn rows(&self) -> Vec<(i64, i32, i32, bool)> {
+        match self {
+            LeaderboardEntries::Sprint(entries) | LeaderboardEntries::Challenge(entries) => entries
+                .iter()
+                .map(|e| (e.steam_id as i64, e.time, e.rank as i32, e.has_replay))
+                .collect(),
+            LeaderboardEntries::Stunt(entries) => entries
+                .iter()
+                .map(|e| (e.steam_id as i64, e.score, e.rank as i32, e.has_replay))
+                .collect(),
+        }
+    }
+}
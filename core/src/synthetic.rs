@@ -0,0 +1,310 @@
+//! Made-up datasets for development databases and benchmarks.
+
+use crate::common::{
+    DistanceData, Level, PublishedFileDetailsSubset, ScoreLeaderboardEntry, TimeLeaderboardEntry,
+    User,
+};
+use crate::data_collection;
+use crate::leaderboard_source::LeaderboardEntry;
+use crate::ranking;
+use rand::rngs::StdRng;
+use rand::seq::{IndexedRandom, index};
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Exp, Pareto};
+use serde_json::{Value as JsonValue, json};
+
+const DISTANCE_APP_ID: u32 = 233610;
+
+/// The lowest individual Steam ID.
+const STEAM_ID_BASE: u64 = 76561197960265728;
+
+/// The ID of the first generated workshop level; later ones count up from it.
+const FIRST_WORKSHOP_LEVEL_ID: i64 = 320_000_000;
+
+/// Workshop levels are created between the game's early access release and
+/// the start of 2024.
+const WORKSHOP_TIME_RANGE: (i64, i64) = (1_410_998_400, 1_704_067_200);
+
+/// The smallest leaderboard sizes of the power law, for official and
+/// workshop levels. Official levels are played far more.
+const OFFICIAL_MIN_ENTRIES: f64 = 200.0;
+const WORKSHOP_MIN_ENTRIES: f64 = 2.0;
+
+/// The chance of an entry tying the one before it, on top of the ties that
+/// come from scores being whole numbers.
+const TIE_PROBABILITY: f64 = 0.05;
+
+const NAME_FIRST: &[&str] = &[
+    "Neon", "Turbo", "Quiet", "Crimson", "Silent", "Rapid", "Lucky", "Frozen", "Cosmic", "Broken",
+    "Solar", "Hyper", "Rusty", "Golden", "Shadow", "Pixel",
+];
+const NAME_SECOND: &[&str] = &[
+    "Racer", "Fox", "Drift", "Comet", "Pilot", "Wolf", "Spark", "Ghost", "Rider", "Byte", "Falcon",
+    "Storm", "Nova", "Tiger", "Echo", "Vortex",
+];
+const TITLE_FIRST: &[&str] = &[
+    "Abyss",
+    "Neon",
+    "Cyber",
+    "Crystal",
+    "Infinite",
+    "Forgotten",
+    "Molten",
+    "Hollow",
+    "Electric",
+    "Twisted",
+    "Silent",
+    "Lost",
+];
+const TITLE_SECOND: &[&str] = &[
+    "Highway",
+    "Descent",
+    "Circuit",
+    "Core",
+    "Rush",
+    "Tunnel",
+    "Spiral",
+    "Gauntlet",
+    "Canyon",
+    "Reactor",
+    "Skyline",
+    "Labyrinth",
+];
+
+/// What to generate. The same configuration always generates the same data.
+#[derive(Debug, Clone, Copy)]
+pub struct SyntheticConfig {
+    /// At most as many as there are in the game.
+    pub official_levels: usize,
+    pub workshop_levels: usize,
+    pub users: usize,
+    /// The exponent of the power law leaderboard sizes follow. Lower values
+    /// make large leaderboards more common.
+    pub size_exponent: f64,
+    pub seed: u64,
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        SyntheticConfig {
+            official_levels: usize::MAX,
+            workshop_levels: 5_000,
+            users: 20_000,
+            size_exponent: 1.2,
+            seed: 0,
+        }
+    }
+}
+
+/// Fabricates a dataset shaped like the one `data_collection` downloads.
+pub fn generate(config: &SyntheticConfig) -> DistanceData {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut data = DistanceData::new();
+
+    data.users = (0..config.users)
+        .map(|i| User {
+            steam_id: STEAM_ID_BASE + 1 + i as u64,
+            name: user_name(&mut rng),
+        })
+        .collect();
+
+    data.levels.extend(
+        data_collection::official_levels()
+            .into_iter()
+            .take(config.official_levels),
+    );
+
+    let mut time_created: Vec<i64> = (0..config.workshop_levels)
+        .map(|_| rng.random_range(WORKSHOP_TIME_RANGE.0..WORKSHOP_TIME_RANGE.1))
+        .collect();
+    time_created.sort_unstable();
+    let mut id = FIRST_WORKSHOP_LEVEL_ID;
+    for time_created in time_created {
+        id += rng.random_range(1..400_000);
+        if let Some(level) = workshop_level(&mut rng, &data.users, id, time_created) {
+            data.levels.push(level);
+        }
+    }
+
+    for level in &mut data.levels {
+        let min_entries = if level.workshop_level_details.is_some() {
+            WORKSHOP_MIN_ENTRIES
+        } else {
+            OFFICIAL_MIN_ENTRIES
+        };
+        let sizes = Pareto::new(min_entries, config.size_exponent)
+            .expect("the leaderboard size exponent must be positive");
+        let max_size = data.users.len();
+        let size = |rng: &mut StdRng| (sizes.sample(rng) as usize).min(max_size);
+
+        if level.is_sprint {
+            let size = size(&mut rng);
+            level.sprint_entries = time_entries(&mut rng, &data.users, size);
+        }
+        if level.is_challenge {
+            let size = size(&mut rng);
+            level.challenge_entries = time_entries(&mut rng, &data.users, size);
+        }
+        if level.is_stunt {
+            let size = size(&mut rng);
+            level.stunt_entries = score_entries(&mut rng, &data.users, size);
+        }
+    }
+
+    data
+}
+
+/// Returns a gamer tag, or rarely no name, as stored for players whose name
+/// couldn't be resolved.
+fn user_name(rng: &mut impl Rng) -> String {
+    if rng.random_bool(0.01) {
+        return String::new();
+    }
+
+    let mut name = format!(
+        "{}{}",
+        NAME_FIRST.choose(rng).unwrap(),
+        NAME_SECOND.choose(rng).unwrap()
+    );
+    if rng.random_bool(0.4) {
+        name += &rng.random_range(1..10_000).to_string();
+    }
+
+    name
+}
+
+/// Returns a workshop level with the details the Steam Web API would list for
+/// it, or `None` if there are no users to author it.
+fn workshop_level(rng: &mut impl Rng, users: &[User], id: i64, time_created: i64) -> Option<Level> {
+    // A few prolific authors make most of the levels
+    let author = users.get((users.len() as f64 * rng.random::<f64>().powi(3)) as usize)?;
+
+    let title = format!(
+        "{} {}",
+        TITLE_FIRST.choose(rng).unwrap(),
+        TITLE_SECOND.choose(rng).unwrap()
+    );
+    let modes: &[&str] = match rng.random_range(0..100) {
+        0..70 => &["Sprint"],
+        70..85 => &["Challenge"],
+        85..95 => &["Stunt"],
+        _ => &["Sprint", "Challenge"],
+    };
+    let difficulty = *["Casual", "Normal", "Advanced", "Expert", "Nightmare"]
+        .choose(rng)
+        .unwrap();
+    let tags: Vec<_> = ["Level"]
+        .iter()
+        .chain(modes)
+        .chain([&difficulty])
+        .map(|tag| json!({ "tag": tag }))
+        .collect();
+
+    let time_updated = if rng.random_bool(0.3) {
+        rng.random_range(time_created..=WORKSHOP_TIME_RANGE.1)
+    } else {
+        time_created
+    };
+    let subscriptions = (Pareto::new(10.0, 1.0).unwrap().sample(rng) as u64).min(1_000_000);
+    let json: JsonValue = json!({
+        "result": 1,
+        "publishedfileid": id.to_string(),
+        "creator": author.steam_id.to_string(),
+        "creator_app_id": DISTANCE_APP_ID,
+        "consumer_app_id": DISTANCE_APP_ID,
+        "filename": format!("{title}.bytes"),
+        "file_size": rng.random_range(20_000..5_000_000).to_string(),
+        "file_url": "",
+        "hcontent_file": rng.random::<u64>().to_string(),
+        "preview_url": "",
+        "hcontent_preview": rng.random::<u64>().to_string(),
+        "title": title,
+        "description": "",
+        "time_created": time_created,
+        "time_updated": time_updated,
+        "visibility": 0,
+        "banned": 0,
+        "ban_reason": "",
+        "subscriptions": subscriptions,
+        "favorited": subscriptions / 10,
+        "lifetime_subscriptions": subscriptions + subscriptions / 4,
+        "lifetime_favorited": subscriptions / 8,
+        "views": subscriptions * 3,
+        "tags": tags,
+    });
+    let details: PublishedFileDetailsSubset =
+        serde_json::from_value(json.clone()).expect("generated invalid workshop details");
+
+    Some(Level {
+        id,
+        name: details.title.clone(),
+        is_sprint: modes.contains(&"Sprint"),
+        is_challenge: modes.contains(&"Challenge"),
+        is_stunt: modes.contains(&"Stunt"),
+        workshop_level_details: Some((details, json)),
+        ..Level::default()
+    })
+}
+
+/// Returns `size` distinct players' entries of a leaderboard, best first,
+/// with their ranks.
+fn ranked_entries(
+    rng: &mut impl Rng,
+    users: &[User],
+    size: usize,
+    best: i32,
+    worst: i32,
+) -> Vec<(LeaderboardEntry, u32)> {
+    // The average gap between consecutive scores that spreads them from the
+    // best score to about the worst
+    let gaps = Exp::new(size as f64 / f64::from((worst - best).abs()).max(1.0)).unwrap();
+    let direction = (worst - best).signum();
+
+    let mut entries = Vec::with_capacity(size);
+    let mut score = best;
+    for (user_index, position) in index::sample(rng, users.len(), size).into_iter().zip(1..) {
+        if position > 1 && !rng.random_bool(TIE_PROBABILITY) {
+            let gap = gaps.sample(rng) as i32;
+            score = score.saturating_add(direction * gap).max(0);
+        }
+
+        entries.push(LeaderboardEntry {
+            steam_id: users[user_index].steam_id,
+            score,
+            has_replay: rng.random_bool(if position <= 10 { 0.8 } else { 0.2 }),
+        });
+    }
+
+    ranking::rank(entries)
+}
+
+/// Returns the entries of a sprint or challenge leaderboard, with times in
+/// milliseconds.
+fn time_entries(rng: &mut impl Rng, users: &[User], size: usize) -> Vec<TimeLeaderboardEntry> {
+    let best = rng.random_range(20_000..180_000);
+
+    ranked_entries(rng, users, size, best, best * 3)
+        .into_iter()
+        .map(|(entry, rank)| TimeLeaderboardEntry {
+            steam_id: entry.steam_id,
+            time: entry.score,
+            rank,
+            has_replay: entry.has_replay,
+        })
+        .collect()
+}
+
+/// Returns the entries of a stunt leaderboard.
+fn score_entries(rng: &mut impl Rng, users: &[User], size: usize) -> Vec<ScoreLeaderboardEntry> {
+    let best = rng.random_range(100_000..5_000_000);
+
+    ranked_entries(rng, users, size, best, 0)
+        .into_iter()
+        .map(|(entry, rank)| ScoreLeaderboardEntry {
+            steam_id: entry.steam_id,
+            score: entry.score,
+            rank,
+            has_replay: entry.has_replay,
+        })
+        .collect()
+}
@@ -0,0 +1,168 @@
+//! Limiting the rate and concurrency of upstream requests.
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
+
+/// Don't halve the rate more than once per this interval, so a burst of
+/// failures doesn't throttle requests all the way down at once.
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(1);
+
+/// The lowest rate the throttle backs off to, in requests per second.
+const MIN_RATE: f64 = 1.0;
+
+/// How hard a [`Throttle`] may hit the upstream.
+#[derive(Debug, Copy, Clone)]
+pub struct ThrottleConfig {
+    /// How many requests may be in flight at once.
+    pub concurrency: usize,
+    /// The highest rate of requests per second.
+    pub max_rate: f64,
+    /// Requests slower than this count as a sign of overload.
+    pub slow_request: Duration,
+}
+
+/// Limits the gRPC requests of a run, across all modes, to a number in flight
+/// and a shared token-bucket rate.
+///
+/// The rate is halved whenever a request fails or is slow, and recovers
+/// gradually as requests succeed again.
+#[derive(Debug)]
+pub struct Throttle {
+    config: ThrottleConfig,
+    in_flight: Semaphore,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    rate: f64,
+    last_refill: Instant,
+    last_decrease: Option<Instant>,
+}
+
+impl Throttle {
+    /// Creates a throttle that starts out at the highest rate allowed.
+    pub fn new(config: ThrottleConfig) -> Self {
+        let config = ThrottleConfig {
+            concurrency: config.concurrency.max(1),
+            max_rate: config.max_rate.max(MIN_RATE),
+            ..config
+        };
+
+        Throttle {
+            config,
+            in_flight: Semaphore::new(config.concurrency),
+            bucket: Mutex::new(Bucket {
+                tokens: 1.0,
+                rate: config.max_rate,
+                last_refill: Instant::now(),
+                last_decrease: None,
+            }),
+        }
+    }
+
+    /// How many requests may be in flight at once.
+    pub fn concurrency(&self) -> usize {
+        self.config.concurrency
+    }
+
+    /// Runs `request` once the throttle allows it, then adjusts the rate to
+    /// how it went.
+    pub async fn run<T, E>(&self, request: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        let _permit = self
+            .in_flight
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        self.take_token().await;
+
+        let start = Instant::now();
+        let result = request.await;
+        self.adjust(result.is_ok(), start.elapsed());
+
+        result
+    }
+
+    async fn take_token(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+                // Allow bursts of up to a second's worth of requests.
+                bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.rate.max(1.0));
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.rate)
+            };
+
+            time::sleep(wait).await;
+        }
+    }
+
+    fn adjust(&self, succeeded: bool, latency: Duration) {
+        let mut bucket = self.bucket.lock().unwrap();
+        if succeeded && latency <= self.config.slow_request {
+            if bucket.rate < self.config.max_rate {
+                bucket.rate =
+                    (bucket.rate + self.config.max_rate / 100.0).min(self.config.max_rate);
+                if bucket.rate == self.config.max_rate {
+                    info!(rate = bucket.rate, "gRPC request rate recovered");
+                }
+            }
+        } else if bucket
+            .last_decrease
+            .is_none_or(|t| t.elapsed() >= DECREASE_COOLDOWN)
+        {
+            bucket.rate = (bucket.rate / 2.0).max(MIN_RATE);
+            bucket.last_decrease = Some(Instant::now());
+            warn!(
+                rate = bucket.rate,
+                succeeded,
+                latency_ms = latency.as_millis() as u64,
+                "Slowing down gRPC requests"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run_all(throttle: &Throttle, n: usize) -> Duration {
+        let start = Instant::now();
+        for _ in 0..n {
+            throttle.run(async { Ok::<_, ()>(()) }).await.unwrap();
+        }
+
+        start.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failures_halve_the_rate() {
+        let throttle = Throttle::new(ThrottleConfig {
+            concurrency: 1,
+            max_rate: 10.0,
+            slow_request: Duration::from_secs(10),
+        });
+
+        // The first request goes right away, and the rest at 10 a second
+        let elapsed = run_all(&throttle, 10).await;
+        assert!(elapsed >= Duration::from_millis(890) && elapsed < Duration::from_secs(1));
+
+        throttle.run(async { Err::<(), _>(()) }).await.unwrap_err();
+        let elapsed = run_all(&throttle, 10).await;
+        assert!(elapsed >= Duration::from_millis(1700), "{elapsed:?}");
+    }
+}
@@ -0,0 +1,63 @@
+//! The services a run downloads data from.
+
+use crate::grpc_pool::GrpcPool;
+use crate::leaderboard_source::{LeaderboardEntry, LeaderboardSource, Leaderboards};
+use anyhow::{Context, Error};
+use futures::{Stream, StreamExt};
+use serde_json::Value as JsonValue;
+
+const DISTANCE_APP_ID: u32 = 233610;
+
+/// The services `data_collection` downloads data from.
+pub trait Upstream: LeaderboardSource {
+    /// Returns the details of every workshop file, a page at a time.
+    fn workshop_pages(&self) -> impl Stream<Item = Result<Vec<JsonValue>, Error>>;
+
+    /// Returns the persona name of each of `steam_ids`, in the same order.
+    fn persona_names(
+        &self,
+        steam_ids: Vec<u64>,
+    ) -> impl Future<Output = Result<Vec<Option<String>>, Error>>;
+
+    /// How many requests may be in flight at once.
+    fn concurrency(&self) -> usize;
+}
+
+/// Steam and the DistanceSteamDataServers.
+#[derive(Debug)]
+pub struct Live<'a> {
+    pub web_client: reqwest::Client,
+    pub web_api_key: String,
+    pub grpc: &'a GrpcPool,
+    pub leaderboards: Leaderboards<'a>,
+}
+
+impl LeaderboardSource for Live<'_> {
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        self.leaderboards
+            .leaderboard_entries_all(leaderboard_name)
+            .await
+    }
+}
+
+impl Upstream for Live<'_> {
+    fn workshop_pages(&self) -> impl Stream<Item = Result<Vec<JsonValue>, Error>> {
+        steam_workshop::query_all_files(
+            self.web_client.clone(),
+            self.web_api_key.clone(),
+            DISTANCE_APP_ID,
+        )
+        .map(|page| page.context("error querying a page of workshop files"))
+    }
+
+    async fn persona_names(&self, steam_ids: Vec<u64>) -> Result<Vec<Option<String>>, Error> {
+        self.grpc.persona_names(steam_ids).await
+    }
+
+    fn concurrency(&self) -> usize {
+        self.grpc.concurrency()
+    }
+}
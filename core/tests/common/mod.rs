@@ -0,0 +1,231 @@
+//! A scripted [`Upstream`] for runs to collect from, and a throwaway database
+//! to store into.
+//!
+//! [`FakeUpstream`] replaces the whole of
+//! [`Live`](distance_db_core::upstream::Live), so none of the code that talks
+//! to Steam or the DistanceSteamDataServers runs in these tests: not the gRPC
+//! client, the workshop paging of the `steam-workshop` crate or the persona
+//! name lookups. Fakes of those services on the wire are out of scope for now;
+//! see the Tests section of the README.
+//!
+//! Test files declare this as `pub mod common;`, so the helpers a file doesn't
+//! use aren't reported as dead code.
+
+use anyhow::{Error, format_err};
+use distance_db_core::common::{DistanceData, RunOptions};
+use distance_db_core::leaderboard_source::{LeaderboardEntry, LeaderboardSource};
+use distance_db_core::report::RunReport;
+use distance_db_core::synthetic::{self, SyntheticConfig};
+use distance_db_core::upstream::Upstream;
+use distance_db_core::{data_collection, data_storing};
+use distance_util::LeaderboardGameMode;
+use futures::{Stream, stream};
+use serde_json::{Value as JsonValue, json};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Mutex, MutexGuard};
+use tokio_postgres::Client;
+
+pub use distance_db_test_db::TestDb;
+
+/// Workshop files are served this many to a page.
+const WORKSHOP_PAGE_SIZE: usize = 2;
+
+/// An [`Upstream`] serving workshop files, leaderboards and persona names set
+/// up by a test.
+///
+/// Leaderboards that weren't set up are empty.
+#[derive(Debug, Default)]
+pub struct FakeUpstream {
+    state: Mutex<FakeState>,
+}
+
+#[derive(Debug, Default)]
+pub struct FakeState {
+    pub workshop_files: Vec<JsonValue>,
+    pub leaderboards: HashMap<String, Result<Vec<LeaderboardEntry>, String>>,
+    pub persona_names: HashMap<u64, String>,
+    pub fail_workshop: bool,
+    pub fail_persona_names: bool,
+}
+
+impl FakeUpstream {
+    pub fn state(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl LeaderboardSource for FakeUpstream {
+    async fn leaderboard_entries_all(
+        &self,
+        leaderboard_name: &str,
+    ) -> Result<Vec<LeaderboardEntry>, Error> {
+        match self.state().leaderboards.get(leaderboard_name) {
+            Some(Ok(entries)) => Ok(entries.clone()),
+            Some(Err(e)) => Err(format_err!("{e}")),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+impl Upstream for FakeUpstream {
+    fn workshop_pages(&self) -> impl Stream<Item = Result<Vec<JsonValue>, Error>> {
+        let state = self.state();
+        let pages: Vec<_> = if state.fail_workshop {
+            vec![Err(format_err!("the workshop is down"))]
+        } else {
+            state
+                .workshop_files
+                .chunks(WORKSHOP_PAGE_SIZE)
+                .map(|page| Ok(page.to_vec()))
+                .collect()
+        };
+
+        stream::iter(pages)
+    }
+
+    async fn persona_names(&self, steam_ids: Vec<u64>) -> Result<Vec<Option<String>>, Error> {
+        let state = self.state();
+        if state.fail_persona_names {
+            return Err(format_err!("the Steam client is offline"));
+        }
+
+        Ok(steam_ids
+            .iter()
+            .map(|steam_id| state.persona_names.get(steam_id).cloned())
+            .collect())
+    }
+
+    fn concurrency(&self) -> usize {
+        4
+    }
+}
+
+/// A workshop level, as it's listed in the Steam Workshop.
+#[derive(Debug, Clone, Copy)]
+pub struct WorkshopLevel {
+    pub id: i64,
+    pub title: &'static str,
+    pub creator: u64,
+    pub mode: LeaderboardGameMode,
+}
+
+impl WorkshopLevel {
+    pub fn json(&self) -> JsonValue {
+        let tag = match self.mode {
+            LeaderboardGameMode::Sprint => "Sprint",
+            LeaderboardGameMode::Challenge => "Challenge",
+            LeaderboardGameMode::Stunt => "Stunt",
+        };
+
+        json!({
+            "publishedfileid": self.id.to_string(),
+            "creator": self.creator.to_string(),
+            "filename": format!("{}.bytes", self.title),
+            "file_size": "1024",
+            "title": self.title,
+            "tags": [{ "tag": tag }],
+            "time_created": 1_600_000_000,
+            "time_updated": 1_600_000_000,
+        })
+    }
+
+    pub fn leaderboard_name(&self) -> String {
+        distance_util::create_leaderboard_name_string(self.title, self.mode, Some(self.creator))
+            .unwrap()
+    }
+}
+
+/// Returns leaderboard entries for `(steam_id, score)` pairs, best first.
+pub fn entries(entries: &[(u64, i32)]) -> Vec<LeaderboardEntry> {
+    entries
+        .iter()
+        .map(|&(steam_id, score)| LeaderboardEntry {
+            steam_id,
+            score,
+            has_replay: false,
+        })
+        .collect()
+}
+
+pub const SPRINT_LEVEL: WorkshopLevel = WorkshopLevel {
+    id: 1001,
+    title: "Sprint Level",
+    creator: 90,
+    mode: LeaderboardGameMode::Sprint,
+};
+
+pub const STUNT_LEVEL: WorkshopLevel = WorkshopLevel {
+    id: 1002,
+    title: "Stunt Level",
+    creator: 91,
+    mode: LeaderboardGameMode::Stunt,
+};
+
+/// Returns a fake upstream listing `levels`, which knows the names of their
+/// authors.
+pub fn upstream(levels: &[WorkshopLevel]) -> FakeUpstream {
+    let upstream = FakeUpstream::default();
+    {
+        let mut state = upstream.state();
+        state.workshop_files = levels.iter().map(WorkshopLevel::json).collect();
+        state.persona_names.insert(90, "Sprint Author".to_owned());
+        state.persona_names.insert(91, "Stunt Author".to_owned());
+    }
+
+    upstream
+}
+
+/// Sets the leaderboard of `level` to `(steam_id, score)` pairs, best first.
+pub fn set_leaderboard(upstream: &FakeUpstream, level: WorkshopLevel, scores: &[(u64, i32)]) {
+    upstream
+        .state()
+        .leaderboards
+        .insert(level.leaderboard_name(), Ok(entries(scores)));
+}
+
+/// Collects data from `upstream` and stores it, like a populator run minus
+/// the safety guard.
+pub async fn populate(
+    db: &mut Client,
+    upstream: &FakeUpstream,
+    options: RunOptions,
+) -> Result<RunReport, Error> {
+    let mut report = RunReport::new();
+    let mut data = data_collection::run(upstream, db, options, &mut report).await?;
+    data_storing::run(db, &mut data, options, &mut report).await?;
+
+    Ok(report)
+}
+
+/// Returns a generated dataset with `workshop_levels` workshop levels and
+/// `users` players.
+pub fn synthetic(workshop_levels: usize, users: usize) -> DistanceData {
+    synthetic::generate(&SyntheticConfig {
+        workshop_levels,
+        users,
+        ..SyntheticConfig::default()
+    })
+}
+
+/// Stores a dataset [`synthetic`] generates in `db`, and returns it.
+pub async fn stored_synthetic(
+    db: &mut Client,
+    workshop_levels: usize,
+    users: usize,
+) -> DistanceData {
+    let mut data = synthetic(workshop_levels, users);
+    data_storing::run(db, &mut data, RunOptions::default(), &mut RunReport::new())
+        .await
+        .unwrap();
+
+    data
+}
+
+/// Returns a directory for a test to write `name` files to, unique to this
+/// process.
+pub fn temp_dir(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("distance-db-{name}-test-{}", process::id()))
+}
@@ -0,0 +1,151 @@
+//! Exports stored or generated data as Parquet files and dumps. The Postgres
+//! tests are ignored unless run with `--ignored`, and then need
+//! `TEST_DATABASE_URL` to point to a Postgres server they may create
+//! databases on.
+
+pub mod common;
+
+use common::{TestDb, stored_synthetic, synthetic, temp_dir};
+use distance_db_core::anonymize::{Anonymizer, AuthorPolicy, NamePolicy};
+use distance_db_core::dump;
+use distance_db_core::parquet_export::{self, ParquetOptions};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::Path;
+#[test]
+fn generated_data_can_be_exported_as_parquet() {
+    let data = synthetic(100, 500);
+    let dir = temp_dir("parquet");
+    let rows = |path: &Path| {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        let version = metadata
+            .key_value_metadata()
+            .and_then(|kv| kv.iter().find(|kv| kv.key == "distance_db_schema_version"))
+            .and_then(|kv| kv.value.clone());
+        assert_eq!(version.as_deref(), Some(parquet_export::SCHEMA_VERSION));
+
+        metadata.num_rows() as usize
+    };
+    let sprint_entries: usize = data.levels.iter().map(|l| l.sprint_entries.len()).sum();
+    let all_entries: usize = data
+        .levels
+        .iter()
+        .map(|l| l.sprint_entries.len() + l.challenge_entries.len() + l.stunt_entries.len())
+        .sum();
+
+    parquet_export::write(&data, &dir, ParquetOptions::default()).unwrap();
+    assert_eq!(rows(&dir.join("levels.parquet")), data.levels.len());
+    assert_eq!(rows(&dir.join("users.parquet")), data.users.len());
+    assert_eq!(rows(&dir.join("workshop_level_details.parquet")), 100);
+    assert_eq!(rows(&dir.join("leaderboard_entries.parquet")), all_entries);
+
+    let options = ParquetOptions {
+        partition_by_mode: true,
+    };
+    parquet_export::write(&data, &dir, options).unwrap();
+    assert_eq!(
+        rows(&dir.join("leaderboard_entries/mode=sprint/part-0.parquet")),
+        sprint_entries
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn stored_data_can_be_dumped() {
+    let mut db = TestDb::create().await;
+    let data = stored_synthetic(&mut db.client, 100, 500).await;
+    let dir = temp_dir("dump");
+
+    let manifest = dump::write(&mut db.client, &dir, None).await.unwrap();
+    assert!(manifest.last_updated.is_some());
+    let users = manifest.tables.iter().find(|t| t.name == "users").unwrap();
+    assert_eq!(users.rows, data.users.len() as u64);
+    for table in &manifest.tables {
+        for file in &table.files {
+            let contents = fs::read(dir.join(&file.path)).unwrap();
+            assert_eq!(
+                format!("{:x}", Sha256::digest(&contents)),
+                file.sha256,
+                "{}",
+                file.path
+            );
+
+            // The CSV files have a header line
+            let lines = contents.iter().filter(|&&b| b == b'\n').count() as u64;
+            let header = u64::from(file.path.ends_with(".csv"));
+            assert_eq!(lines, table.rows + header, "{}", file.path);
+        }
+    }
+    let written: JsonValue =
+        serde_json::from_slice(&fs::read(dir.join("manifest.json")).unwrap()).unwrap();
+    assert_eq!(written["format_version"], dump::FORMAT_VERSION);
+
+    fs::remove_dir_all(&dir).unwrap();
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn anonymized_dumps_pseudonymize_players() {
+    let mut db = TestDb::create().await;
+    let data = stored_synthetic(&mut db.client, 100, 500).await;
+    let opted_in = data
+        .levels
+        .iter()
+        .find_map(|l| Some(l.workshop_level_details.as_ref()?.0.creator))
+        .unwrap();
+    let anonymizer = Anonymizer::new(
+        b"not a very secret key",
+        NamePolicy::Redact,
+        AuthorPolicy::Redact,
+        HashSet::from([opted_in]),
+    )
+    .unwrap();
+    let dir = temp_dir("anonymized");
+
+    dump::write(&mut db.client, &dir, Some(&anonymizer))
+        .await
+        .unwrap();
+    let rows = |table: &str| -> Vec<JsonValue> {
+        fs::read_to_string(dir.join(format!("{table}.jsonl")))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    };
+
+    // Everyone but the opted-in author is pseudonymized and nameless, the same
+    // way in every table
+    let users = rows("users");
+    let steam_ids: HashSet<_> = users
+        .iter()
+        .map(|u| u["steam_id"].as_i64().unwrap())
+        .collect();
+    for user in &users {
+        let opted_in = user["steam_id"] == opted_in;
+        assert_eq!(user["steam_id"].as_i64().unwrap() < 0, !opted_in);
+        assert_eq!(user["name"] == "", !opted_in);
+    }
+    for entry in rows("sprint_leaderboard_entries") {
+        assert!(steam_ids.contains(&entry["steam_id"].as_i64().unwrap()));
+    }
+
+    // Only the opted-in author's levels keep their author
+    for details in rows("workshop_level_details") {
+        let author = &details["author_steam_id"];
+        assert!(author.is_null() || *author == opted_in);
+        assert_eq!(
+            details["raw_details"].get("creator").is_some(),
+            !author.is_null()
+        );
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+    db.destroy().await;
+}
@@ -0,0 +1,51 @@
+//! Checks which spans are exported as traces.
+
+use distance_db_core::logging::{self, Service};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use tracing::{debug_span, info_span};
+use tracing_subscriber::Registry;
+use tracing_subscriber::prelude::*;
+
+const SERVICE: Service = Service {
+    name: "distance-db-populator",
+    target: "distance_db_populator",
+};
+
+#[test]
+fn runs_are_exported_as_one_trace() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = Registry::default().with(logging::otel_layer(&provider, SERVICE));
+
+    tracing::subscriber::with_default(subscriber, || {
+        // The binary's root span, the library's phases, and a dependency's
+        let _run = info_span!(target: "distance_db_populator", "populator_run").entered();
+        let _phase = info_span!(target: "distance_db_core::data_storing", "store_users").entered();
+        let _call = debug_span!(target: "distance_db_core::grpc_pool", "grpc_call").entered();
+        let _other = info_span!(target: "h2", "poll").entered();
+    });
+    provider.force_flush().unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let names: Vec<_> = spans.iter().map(|span| span.name.as_ref()).collect();
+    assert_eq!(names.len(), 3, "{names:?}");
+    let root = spans
+        .iter()
+        .find(|span| span.name == "populator_run")
+        .expect("the root span is exported");
+    for span in &spans {
+        assert_eq!(
+            span.span_context.trace_id(),
+            root.span_context.trace_id(),
+            "{}",
+            span.name
+        );
+    }
+    let phase = spans
+        .iter()
+        .find(|span| span.name == "store_users")
+        .unwrap();
+    assert_eq!(phase.parent_span_id, root.span_context.span_id());
+}
@@ -0,0 +1,417 @@
+//! Drives whole populator runs against a scripted upstream and a throwaway
+//! database, and stores generated data into one. The Postgres tests are
+//! ignored unless run with `--ignored`, and then need `TEST_DATABASE_URL` to
+//! point to a Postgres server they may create databases on.
+
+pub mod common;
+
+use common::{
+    SPRINT_LEVEL, STUNT_LEVEL, TestDb, populate, set_leaderboard, stored_synthetic, synthetic,
+    upstream,
+};
+use distance_db_core::common::{RunOptions, TimeLeaderboardEntry};
+use distance_db_core::failure::{EXIT_PARTIAL, FailureKind};
+use distance_db_core::report::RunStatus;
+use distance_db_core::{db, opt_out};
+use std::collections::HashSet;
+use tokio_postgres::Client;
+
+/// Returns the `(steam_id, time or score, rank, has_replay)` rows stored for
+/// a level, best first.
+async fn stored_entries(
+    db: &Client,
+    table: &str,
+    column: &str,
+    level_id: i64,
+) -> Vec<(i64, i32, i32, bool)> {
+    db.query(
+        &format!(
+            "SELECT steam_id, {column}, rank, has_replay FROM {table} WHERE level_id = $1 ORDER BY rank, steam_id"
+        ),
+        &[&level_id],
+    )
+    .await
+    .unwrap()
+    .iter()
+    .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3)))
+    .collect()
+}
+
+async fn sprint_entries(db: &Client, level_id: i64) -> Vec<(i64, i32, i32, bool)> {
+    stored_entries(db, "sprint_leaderboard_entries", "time", level_id).await
+}
+
+async fn user_name(db: &Client, steam_id: i64) -> Option<String> {
+    db.query_opt("SELECT name FROM users WHERE steam_id = $1", &[&steam_id])
+        .await
+        .unwrap()
+        .map(|row| row.get(0))
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn tied_entries_share_a_rank() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL, STUNT_LEVEL]);
+    set_leaderboard(
+        &upstream,
+        SPRINT_LEVEL,
+        &[
+            (1, 100),
+            (2, 100),
+            (3, 200),
+            (4, 300),
+            (5, 300),
+            (6, 300),
+            (7, 400),
+        ],
+    );
+    set_leaderboard(&upstream, STUNT_LEVEL, &[(1, 900), (2, 800), (3, 800)]);
+
+    let report = populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    let store_entries = report
+        .phases
+        .iter()
+        .find(|phase| phase.name == "store_entries")
+        .unwrap();
+    assert_eq!(store_entries.items, 10);
+
+    let ranks: Vec<_> = sprint_entries(&db.client, SPRINT_LEVEL.id)
+        .await
+        .into_iter()
+        .map(|(steam_id, _, rank, _)| (steam_id, rank))
+        .collect();
+    assert_eq!(
+        ranks,
+        [(1, 1), (2, 1), (3, 3), (4, 4), (5, 4), (6, 4), (7, 7)]
+    );
+
+    let stunt = stored_entries(
+        &db.client,
+        "stunt_leaderboard_entries",
+        "score",
+        STUNT_LEVEL.id,
+    )
+    .await;
+    assert_eq!(
+        stunt,
+        [(1, 900, 1, false), (2, 800, 2, false), (3, 800, 2, false)]
+    );
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn unchanged_leaderboards_are_not_rewritten() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100), (2, 200)]);
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+
+    // Change the stored entries behind the populator's back. Since the
+    // leaderboard's hash still matches, the next run leaves them alone.
+    let tamper = "UPDATE sprint_leaderboard_entries SET has_replay = true";
+    db.client.batch_execute(tamper).await.unwrap();
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        sprint_entries(&db.client, SPRINT_LEVEL.id).await,
+        [(1, 100, 1, true), (2, 200, 2, true)]
+    );
+
+    // A forced rebuild rewrites them anyway
+    let options = RunOptions {
+        force_rebuild: true,
+        ..RunOptions::default()
+    };
+    populate(&mut db.client, &upstream, options).await.unwrap();
+    assert_eq!(
+        sprint_entries(&db.client, SPRINT_LEVEL.id).await,
+        [(1, 100, 1, false), (2, 200, 2, false)]
+    );
+
+    // So does a change upstream
+    db.client.batch_execute(tamper).await.unwrap();
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(3, 50), (1, 100), (2, 200)]);
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        sprint_entries(&db.client, SPRINT_LEVEL.id).await,
+        [(3, 50, 1, false), (1, 100, 2, false), (2, 200, 3, false)]
+    );
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn renamed_users_are_updated() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100), (2, 200)]);
+    upstream
+        .state()
+        .persona_names
+        .insert(1, "Old Name".to_owned());
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(user_name(&db.client, 1).await.as_deref(), Some("Old Name"));
+    // Players whose name couldn't be resolved are stored without one
+    assert_eq!(user_name(&db.client, 2).await.as_deref(), Some(""));
+    assert_eq!(
+        user_name(&db.client, 90).await.as_deref(),
+        Some("Sprint Author")
+    );
+
+    upstream
+        .state()
+        .persona_names
+        .insert(1, "New Name".to_owned());
+    upstream
+        .state()
+        .persona_names
+        .insert(2, "Found Name".to_owned());
+
+    // Only new players are looked up with `only_new_names`
+    let options = RunOptions {
+        only_new_names: true,
+        ..RunOptions::default()
+    };
+    populate(&mut db.client, &upstream, options).await.unwrap();
+    assert_eq!(user_name(&db.client, 1).await.as_deref(), Some("Old Name"));
+
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(user_name(&db.client, 1).await.as_deref(), Some("New Name"));
+    assert_eq!(
+        user_name(&db.client, 2).await.as_deref(),
+        Some("Found Name")
+    );
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn opted_out_names_are_hidden() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100), (2, 200)]);
+    upstream.state().persona_names.insert(1, "One".to_owned());
+    upstream.state().persona_names.insert(2, "Two".to_owned());
+
+    assert_eq!(opt_out::add(&db.client, &[1, 1]).await.unwrap(), 1);
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(
+        user_name(&db.client, 1).await.as_deref(),
+        Some(opt_out::HIDDEN_NAME)
+    );
+    assert_eq!(user_name(&db.client, 2).await.as_deref(), Some("Two"));
+    // Their entries are kept
+    assert_eq!(
+        sprint_entries(&db.client, SPRINT_LEVEL.id).await,
+        [(1, 100, 1, false), (2, 200, 2, false)]
+    );
+
+    // Names stored before opting out stay until they're purged
+    opt_out::add(&db.client, &[2]).await.unwrap();
+    assert_eq!(user_name(&db.client, 2).await.as_deref(), Some("Two"));
+    assert_eq!(opt_out::purge(&db.client).await.unwrap(), 1);
+    assert_eq!(
+        user_name(&db.client, 2).await.as_deref(),
+        Some(opt_out::HIDDEN_NAME)
+    );
+
+    assert_eq!(opt_out::remove(&db.client, &[1, 3]).await.unwrap(), 1);
+    assert_eq!(opt_out::list(&db.client).await.unwrap(), HashSet::from([2]));
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(user_name(&db.client, 1).await.as_deref(), Some("One"));
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn removed_levels_are_kept() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL, STUNT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100)]);
+    set_leaderboard(&upstream, STUNT_LEVEL, &[(1, 900)]);
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+
+    // The stunt level is removed from the workshop
+    upstream.state().workshop_files = vec![SPRINT_LEVEL.json()];
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+
+    let row = db
+        .client
+        .query_one(
+            "SELECT name, stunt_leaderboard_hash IS NOT NULL FROM workshop_levels WHERE id = $1",
+            &[&STUNT_LEVEL.id],
+        )
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, String>(0), STUNT_LEVEL.title);
+    assert!(row.get::<_, bool>(1));
+    assert_eq!(
+        stored_entries(
+            &db.client,
+            "stunt_leaderboard_entries",
+            "score",
+            STUNT_LEVEL.id
+        )
+        .await,
+        [(1, 900, 1, false)]
+    );
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn failed_leaderboards_keep_their_stored_entries() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100), (2, 200)]);
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+
+    upstream
+        .state()
+        .leaderboards
+        .insert(SPRINT_LEVEL.leaderboard_name(), Err("timed out".to_owned()));
+    let mut report = populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(report.skipped_leaderboards.get("sprint"), Some(&1));
+    assert_eq!(report.finish(&Ok(())), EXIT_PARTIAL);
+    assert_eq!(report.status, RunStatus::Partial);
+    assert_eq!(
+        sprint_entries(&db.client, SPRINT_LEVEL.id).await,
+        [(1, 100, 1, false), (2, 200, 2, false)]
+    );
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn upstream_failures_fail_the_run() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100)]);
+
+    upstream.state().fail_workshop = true;
+    let error = populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(FailureKind::of(&error), Some(FailureKind::Upstream));
+
+    upstream.state().fail_workshop = false;
+    upstream.state().fail_persona_names = true;
+    let error = populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap_err();
+    assert_eq!(FailureKind::of(&error), Some(FailureKind::Upstream));
+
+    // Nothing was stored
+    let levels: i64 = db
+        .client
+        .query_one("SELECT count(*) FROM levels", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(levels, 0);
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn generated_data_can_be_stored() {
+    let mut db = TestDb::create().await;
+    let data = stored_synthetic(&mut db.client, 200, 1_000).await;
+
+    let row = db
+        .client
+        .query_one(
+            "SELECT (SELECT count(*) FROM users), (SELECT count(*) FROM levels), (SELECT count(*) FROM workshop_levels)",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, i64>(0), data.users.len() as i64);
+    assert_eq!(row.get::<_, i64>(1), data.levels.len() as i64);
+    assert_eq!(row.get::<_, i64>(2), 200);
+
+    // Ranks count the players ahead, with tied players sharing a rank
+    let misranked: i64 = db
+        .client
+        .query_one(
+            "SELECT count(*) FROM sprint_leaderboard_entries e WHERE rank <> 1 + (SELECT count(*) FROM sprint_leaderboard_entries o WHERE o.level_id = e.level_id AND o.time < e.time)",
+            &[],
+        )
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(misranked, 0);
+
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn stored_data_loads_back() {
+    let mut db = TestDb::create().await;
+    let data = stored_synthetic(&mut db.client, 100, 500).await;
+
+    let loaded = db::load(&mut db.client).await.unwrap();
+    assert_eq!(loaded.users.len(), data.users.len());
+    assert_eq!(
+        loaded.levels.iter().map(|l| l.id).collect::<Vec<_>>(),
+        data.levels.iter().map(|l| l.id).collect::<Vec<_>>()
+    );
+    for (loaded, level) in loaded.levels.iter().zip(&data.levels) {
+        let key = |e: &TimeLeaderboardEntry| (e.rank, e.steam_id);
+        let mut sprint_entries = level.sprint_entries.clone();
+        sprint_entries.sort_by_key(key);
+        assert_eq!(
+            format!("{:?}", loaded.sprint_entries),
+            format!("{sprint_entries:?}")
+        );
+        assert_eq!(loaded.stunt_entries.len(), level.stunt_entries.len());
+        assert_eq!(
+            loaded.workshop_level_details.as_ref().map(|(_, json)| json),
+            level.workshop_level_details.as_ref().map(|(_, json)| json)
+        );
+    }
+
+    db.destroy().await;
+}
+
+#[test]
+fn generation_is_deterministic() {
+    let a = synthetic(50, 100);
+    let b = synthetic(50, 100);
+
+    assert_eq!(format!("{a:?}"), format!("{b:?}"));
+}
@@ -0,0 +1,222 @@
+//! Drives `populate::run` itself, replaying fixtures recorded from a
+//! [`FakeUpstream`], to check what a run does around collecting: the lock, the
+//! safety guard, the opt-out list and the copies and exports. Ignored unless
+//! run with `--ignored`, and then needs `TEST_DATABASE_URL` to point to a
+//! Postgres server it may create databases on.
+
+pub mod common;
+
+use arrow_array::StringArray;
+use clap::Parser;
+use common::{FakeUpstream, SPRINT_LEVEL, TestDb, set_leaderboard, temp_dir, upstream};
+use distance_db_core::common::RunOptions;
+use distance_db_core::failure::FailureKind;
+use distance_db_core::fixtures::Recorder;
+use distance_db_core::populate::{self, Args};
+use distance_db_core::report::RunReport;
+use distance_db_core::{data_collection, lock, opt_out};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::Value as JsonValue;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::Duration;
+
+/// Records what a run collects from `upstream` to `dir`, to be replayed.
+async fn record(db: &TestDb, upstream: FakeUpstream, dir: &Path) {
+    let recorder = Recorder::new(upstream);
+    data_collection::run(
+        &recorder,
+        &db.client,
+        RunOptions::default(),
+        &mut RunReport::new(),
+    )
+    .await
+    .unwrap();
+    recorder.save(dir).unwrap();
+}
+
+/// Returns the arguments of a run storing into `db` what was recorded to
+/// `fixtures`, followed by `extra` ones.
+fn args(db: &TestDb, fixtures: &Path, extra: &[&str]) -> Args {
+    let fixtures = fixtures.to_str().unwrap();
+    let args = [
+        "distance-db-populator",
+        "--database-url",
+        &db.url,
+        "--replay-fixtures",
+        fixtures,
+    ];
+
+    Args::try_parse_from(args.iter().chain(extra)).unwrap()
+}
+
+async fn run(args: &Args) -> Result<(), anyhow::Error> {
+    populate::run(args, &mut RunReport::new()).await
+}
+
+async fn sprint_entries(db: &TestDb) -> i64 {
+    db.client
+        .query_one(
+            "SELECT count(*) FROM sprint_leaderboard_entries WHERE level_id = $1",
+            &[&SPRINT_LEVEL.id],
+        )
+        .await
+        .unwrap()
+        .get(0)
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn runs_hide_opted_out_names_everywhere() {
+    let db = TestDb::create().await;
+    let dir = temp_dir("run-outputs");
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100), (2, 200)]);
+    upstream.state().persona_names.insert(1, "One".to_owned());
+    upstream.state().persona_names.insert(2, "Two".to_owned());
+    record(&db, upstream, &dir.join("fixtures")).await;
+    opt_out::add(&db.client, &[2]).await.unwrap();
+
+    let sqlite_copy = dir.join("copy.sqlite");
+    let parquet_export = dir.join("parquet");
+    let static_api = dir.join("api");
+    let args = args(
+        &db,
+        &dir.join("fixtures"),
+        &[
+            "--sqlite-copy",
+            sqlite_copy.to_str().unwrap(),
+            "--parquet-export",
+            parquet_export.to_str().unwrap(),
+            "--static-api",
+            static_api.to_str().unwrap(),
+        ],
+    );
+    run(&args).await.unwrap();
+
+    let names: Vec<(i64, String)> = db
+        .client
+        .query(
+            "SELECT steam_id, name FROM users WHERE steam_id IN (1, 2) ORDER BY steam_id",
+            &[],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    let expected = [(1, "One".to_owned()), (2, opt_out::HIDDEN_NAME.to_owned())];
+    assert_eq!(names, expected);
+
+    let sqlite = rusqlite::Connection::open(&sqlite_copy).unwrap();
+    let name: String = sqlite
+        .query_row("SELECT name FROM users WHERE steam_id = 2", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(name, opt_out::HIDDEN_NAME);
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(
+        File::open(parquet_export.join("users.parquet")).unwrap(),
+    )
+    .unwrap()
+    .build()
+    .unwrap();
+    let mut parquet_names = Vec::new();
+    for batch in reader {
+        let batch = batch.unwrap();
+        let column = batch.column_by_name("name").unwrap();
+        let column = column.as_any().downcast_ref::<StringArray>().unwrap();
+        parquet_names.extend(column.iter().flatten().map(str::to_owned));
+    }
+    assert!(parquet_names.iter().any(|name| name == "One"));
+    assert!(parquet_names.iter().all(|name| name != "Two"));
+
+    let read = |path: &str| -> JsonValue {
+        serde_json::from_slice(&fs::read(static_api.join(path)).unwrap()).unwrap()
+    };
+    assert_eq!(read("players/2.json")["name"], opt_out::HIDDEN_NAME);
+    assert_eq!(
+        read("levels/1001/sprint.json")["entries"][1]["name"],
+        opt_out::HIDDEN_NAME
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn the_safety_guard_is_off_unless_configured() {
+    let db = TestDb::create().await;
+    let dir = temp_dir("run-safety-guard");
+    let full = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(
+        &full,
+        SPRINT_LEVEL,
+        &[(1, 100), (2, 200), (3, 300), (4, 400)],
+    );
+    record(&db, full, &dir.join("full")).await;
+    run(&args(&db, &dir.join("full"), &[])).await.unwrap();
+    assert_eq!(sprint_entries(&db).await, 4);
+
+    let shrunk = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&shrunk, SPRINT_LEVEL, &[(1, 100)]);
+    record(&db, shrunk, &dir.join("shrunk")).await;
+
+    let guarded = args(
+        &db,
+        &dir.join("shrunk"),
+        &["--max-entry-drop-percent", "50"],
+    );
+    let e = run(&guarded).await.unwrap_err();
+    assert_eq!(FailureKind::of(&e), Some(FailureKind::SafetyGuard));
+    assert_eq!(sprint_entries(&db).await, 4);
+
+    run(&args(&db, &dir.join("shrunk"), &[])).await.unwrap();
+    assert_eq!(sprint_entries(&db).await, 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn runs_fail_while_another_holds_the_lock() {
+    let db = TestDb::create().await;
+    let dir = temp_dir("run-lock");
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100)]);
+    record(&db, upstream, &dir).await;
+
+    // The test's own session stands in for another populator
+    lock::acquire(&db.client, Duration::ZERO).await.unwrap();
+    let e = run(&args(&db, &dir, &[])).await.unwrap_err();
+    assert_eq!(FailureKind::of(&e), Some(FailureKind::Locked));
+    assert_eq!(sprint_entries(&db).await, 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+    db.destroy().await;
+}
+
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn runs_create_a_missing_opt_out_list() {
+    let db = TestDb::create().await;
+    let dir = temp_dir("run-opt-out-list");
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100)]);
+    record(&db, upstream, &dir).await;
+
+    // As on a database created before the list existed
+    db.client
+        .batch_execute("DROP TABLE name_opt_outs")
+        .await
+        .unwrap();
+    run(&args(&db, &dir, &[])).await.unwrap();
+    assert_eq!(sprint_entries(&db).await, 1);
+    assert!(opt_out::list(&db.client).await.unwrap().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+    db.destroy().await;
+}
@@ -0,0 +1,65 @@
+//! Stores generated data into an in-memory SQLite database.
+
+pub mod common;
+
+use common::synthetic;
+use distance_db_core::common::{DistanceData, RunOptions};
+use distance_db_core::data_storing;
+use distance_db_core::report::RunReport;
+use distance_db_core::sqlite_storage::{self, SqliteStorage};
+use std::path::Path;
+async fn store_in_sqlite(sqlite: &mut rusqlite::Connection, data: &DistanceData) {
+    let storage = SqliteStorage::begin(sqlite).unwrap();
+    data_storing::store(storage, data, RunOptions::default(), &mut RunReport::new())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn generated_data_can_be_stored_in_sqlite() {
+    let mut data = synthetic(200, 1_000);
+    let mut sqlite = sqlite_storage::open(Path::new(":memory:")).unwrap();
+    store_in_sqlite(&mut sqlite, &data).await;
+
+    let counts = sqlite
+        .query_row(
+            "SELECT (SELECT count(*) FROM users), (SELECT count(*) FROM official_levels), (SELECT count(*) FROM workshop_levels), (SELECT count(*) FROM sprint_leaderboard_entries)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .unwrap();
+    let sprint_entries: usize = data.levels.iter().map(|l| l.sprint_entries.len()).sum();
+    assert_eq!(
+        counts,
+        (
+            data.users.len(),
+            data.levels.len() - 200,
+            200,
+            sprint_entries
+        )
+    );
+
+    // Storing again rewrites changed leaderboards
+    let level = data
+        .levels
+        .iter_mut()
+        .find(|l| l.sprint_entries.len() > 1)
+        .unwrap();
+    let level_id = level.id;
+    level.sprint_entries.pop();
+    let remaining = level.sprint_entries.len();
+    store_in_sqlite(&mut sqlite, &data).await;
+
+    let stored: usize = sqlite
+        .query_row(
+            "SELECT count(*) FROM sprint_leaderboard_entries WHERE level_id = ?1",
+            [level_id],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(stored, remaining);
+    let last_updated: Option<String> = sqlite
+        .query_row("SELECT last_updated FROM metadata", [], |row| row.get(0))
+        .unwrap();
+    assert!(last_updated.is_some());
+}
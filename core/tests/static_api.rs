@@ -0,0 +1,68 @@
+//! Generates the static JSON API from a populated database. Ignored unless
+//! run with `--ignored`, and then needs `TEST_DATABASE_URL` to point to a
+//! Postgres server it may create databases on.
+
+pub mod common;
+
+use common::{SPRINT_LEVEL, TestDb, populate, set_leaderboard, temp_dir, upstream};
+use distance_db_core::common::RunOptions;
+use distance_db_core::static_api;
+use serde_json::Value as JsonValue;
+use std::fs;
+#[tokio::test]
+#[ignore = "needs TEST_DATABASE_URL"]
+async fn static_api_only_rewrites_changed_files() {
+    let mut db = TestDb::create().await;
+    let upstream = upstream(&[SPRINT_LEVEL]);
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(1, 100), (2, 200)]);
+    upstream.state().persona_names.insert(1, "One".to_owned());
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    let dir = temp_dir("static-api");
+    let read = |path: &str| -> JsonValue {
+        serde_json::from_slice(&fs::read(dir.join(path)).unwrap()).unwrap()
+    };
+
+    let first = static_api::write(&mut db.client, &dir).await.unwrap();
+    assert_eq!(first.unchanged, 0);
+    let leaderboard = read("levels/1001/sprint.json");
+    assert_eq!(leaderboard["entries"][0]["steam_id"], "1");
+    assert_eq!(leaderboard["entries"][0]["name"], "One");
+    assert_eq!(leaderboard["entries"][1]["time"], 200);
+    let player = read("players/2.json");
+    assert_eq!(player["entries"][0]["level_id"], SPRINT_LEVEL.id);
+    assert_eq!(player["entries"][0]["rank"], 2);
+    let index = read("levels.json");
+    let level = index["levels"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|level| level["id"] == SPRINT_LEVEL.id)
+        .unwrap();
+    assert_eq!(level["leaderboards"][0]["entries"], 2);
+
+    let summary = static_api::write(&mut db.client, &dir).await.unwrap();
+    assert_eq!((summary.written, summary.unchanged), (0, first.written));
+
+    set_leaderboard(&upstream, SPRINT_LEVEL, &[(2, 50), (1, 100)]);
+    populate(&mut db.client, &upstream, RunOptions::default())
+        .await
+        .unwrap();
+    // Both runs may have finished within the same second
+    db.client
+        .batch_execute("UPDATE metadata SET last_updated = last_updated + interval '1 minute'")
+        .await
+        .unwrap();
+    let summary = static_api::write(&mut db.client, &dir).await.unwrap();
+    // The level index, the leaderboard, its two players and the WR changes
+    assert_eq!(summary.written, 5);
+    assert_eq!(summary.wr_changes, 1);
+    let change = &read("wr_changes.json")["changes"][0];
+    assert_eq!(change["record"]["steam_id"], "2");
+    assert_eq!(change["record"]["time"], 50);
+    assert_eq!(change["previous"]["name"], "One");
+
+    fs::remove_dir_all(&dir).unwrap();
+    db.destroy().await;
+}
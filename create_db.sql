@@ -69,6 +69,12 @@ CREATE TABLE
         CHECK (onerow_id)
     );
 
+CREATE TABLE
+    name_opt_outs (
+        steam_id bigint PRIMARY KEY CHECK (steam_id <> 0),
+        added timestamp with time zone NOT NULL DEFAULT now()
+    );
+
 CREATE VIEW
     official_levels AS
 SELECT
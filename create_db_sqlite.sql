@@ -0,0 +1,114 @@
+CREATE TABLE
+    levels (
+        id integer PRIMARY KEY,
+        name text NOT NULL,
+        is_sprint boolean NOT NULL,
+        is_challenge boolean NOT NULL,
+        is_stunt boolean NOT NULL,
+        sprint_leaderboard_hash integer,
+        challenge_leaderboard_hash integer,
+        stunt_leaderboard_hash integer
+    );
+
+CREATE TABLE
+    users (
+        steam_id integer PRIMARY KEY CHECK (steam_id <> 0),
+        name text NOT NULL
+    );
+
+CREATE TABLE
+    workshop_level_details (
+        level_id integer PRIMARY KEY REFERENCES levels CHECK (
+            level_id = CAST(raw_details ->> 'publishedfileid' AS integer)
+        ),
+        -- JSON object
+        raw_details text NOT NULL,
+        -- JSON array of strings
+        tags text NOT NULL,
+        author_steam_id integer GENERATED ALWAYS AS (CAST(raw_details ->> 'creator' AS integer)) STORED REFERENCES users,
+        time_created text GENERATED ALWAYS AS (
+            strftime('%Y-%m-%dT%H:%M:%SZ', CAST(raw_details ->> 'time_created' AS integer), 'unixepoch')
+        ) STORED NOT NULL,
+        time_updated text GENERATED ALWAYS AS (
+            strftime('%Y-%m-%dT%H:%M:%SZ', CAST(raw_details ->> 'time_updated' AS integer), 'unixepoch')
+        ) STORED NOT NULL
+    );
+
+CREATE TABLE
+    sprint_leaderboard_entries (
+        level_id integer REFERENCES levels,
+        steam_id integer REFERENCES users,
+        time integer NOT NULL,
+        rank integer NOT NULL CHECK (rank > 0),
+        has_replay boolean NOT NULL,
+        PRIMARY KEY (level_id, steam_id)
+    );
+
+CREATE TABLE
+    challenge_leaderboard_entries (
+        level_id integer REFERENCES levels,
+        steam_id integer REFERENCES users,
+        time integer NOT NULL,
+        rank integer NOT NULL CHECK (rank > 0),
+        has_replay boolean NOT NULL,
+        PRIMARY KEY (level_id, steam_id)
+    );
+
+CREATE TABLE
+    stunt_leaderboard_entries (
+        level_id integer REFERENCES levels,
+        steam_id integer REFERENCES users,
+        score integer NOT NULL,
+        rank integer NOT NULL CHECK (rank > 0),
+        has_replay boolean NOT NULL,
+        PRIMARY KEY (level_id, steam_id)
+    );
+
+CREATE TABLE
+    metadata (
+        onerow_id boolean DEFAULT true PRIMARY KEY,
+        last_updated text,
+        CHECK (onerow_id)
+    );
+
+CREATE VIEW
+    official_levels AS
+SELECT
+    *
+FROM
+    levels
+WHERE
+    id NOT IN (
+        SELECT
+            level_id
+        FROM
+            workshop_level_details
+    );
+
+CREATE VIEW
+    workshop_levels AS
+SELECT
+    *
+FROM
+    levels
+WHERE
+    id IN (
+        SELECT
+            level_id
+        FROM
+            workshop_level_details
+    );
+
+CREATE INDEX users_name_idx ON users (name);
+
+CREATE INDEX sprint_leaderboard_entries_level_id_rank_idx ON sprint_leaderboard_entries (level_id, rank);
+
+CREATE INDEX sprint_leaderboard_entries_steam_id_idx ON sprint_leaderboard_entries (steam_id);
+
+CREATE INDEX challenge_leaderboard_entries_level_id_rank_idx ON challenge_leaderboard_entries (level_id, rank);
+
+CREATE INDEX challenge_leaderboard_entries_steam_id_idx ON challenge_leaderboard_entries (steam_id);
+
+CREATE INDEX stunt_leaderboard_entries_level_id_rank_idx ON stunt_leaderboard_entries (level_id, rank);
+
+CREATE INDEX stunt_leaderboard_entries_steam_id_idx ON stunt_leaderboard_entries (steam_id);
@@ -0,0 +1,121 @@
+use crate::runs::{PopulatorArgs, RunRecord, Runs};
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use log::info;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+
+/// Shared state of the admin API.
+#[derive(Debug, Clone)]
+pub struct AdminState {
+    pub token: Arc<str>,
+    pub runs: Arc<Runs>,
+    pub triggers: mpsc::Sender<PopulatorArgs>,
+    pub paused: Arc<watch::Sender<bool>>,
+}
+
+/// Serves the admin API on `address` until the process exits. Every request
+/// must carry an `Authorization: Bearer <ADMIN_TOKEN>` header.
+pub async fn serve(address: SocketAddr, state: AdminState) -> Result<()> {
+    let app = Router::new()
+        .route("/runs", get(list_runs).post(trigger_run))
+        .route("/runs/current", get(current_run))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .layer(middleware::from_fn_with_state(state.clone(), authenticate))
+        .with_state(state);
+
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Couldn't bind the admin API to {address}"))?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn authenticate(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), state.token.as_bytes()));
+
+    if authorized {
+        next.run(request).await
+    } else {
+        error_response(StatusCode::UNAUTHORIZED, "missing or invalid bearer token")
+    }
+}
+
+async fn trigger_run(State(state): State<AdminState>, body: Bytes) -> Response {
+    let args = if body.iter().all(u8::is_ascii_whitespace) {
+        PopulatorArgs::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(args) => args,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{e}")),
+        }
+    };
+
+    if state.runs.current().is_some() {
+        return error_response(StatusCode::CONFLICT, "a run is already in progress");
+    }
+
+    match state.triggers.try_send(args.clone()) {
+        Ok(()) => {
+            info!("Run requested through the admin API: {args:?}");
+            (StatusCode::ACCEPTED, Json(json!({ "queued": args }))).into_response()
+        }
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            error_response(StatusCode::CONFLICT, "a run is already queued")
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the manager is shutting down",
+        ),
+    }
+}
+
+async fn current_run(State(state): State<AdminState>) -> Json<Option<RunRecord>> {
+    Json(state.runs.current())
+}
+
+async fn list_runs(State(state): State<AdminState>) -> Json<Vec<RunRecord>> {
+    Json(state.runs.recent())
+}
+
+async fn pause(State(state): State<AdminState>) -> Response {
+    set_paused(&state, true)
+}
+
+async fn resume(State(state): State<AdminState>) -> Response {
+    set_paused(&state, false)
+}
+
+fn set_paused(state: &AdminState, paused: bool) -> Response {
+    state.paused.send_replace(paused);
+    info!(
+        "Schedule {} through the admin API",
+        if paused { "paused" } else { "resumed" }
+    );
+
+    Json(json!({ "paused": paused })).into_response()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
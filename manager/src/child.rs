@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::process::Child;
+use tokio::signal::unix::{Signal as SignalStream, SignalKind, signal as unix_signal};
+use tokio::time;
+
+/// How long a child gets to exit after being sent a termination signal,
+/// before it is killed outright.
+const TERMINATION_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// How a supervised child process ended.
+#[derive(Debug)]
+pub enum Outcome {
+    /// The child exited on its own.
+    Exited(ExitStatus),
+    /// The child ran past its deadline and was terminated.
+    TimedOut(ExitStatus),
+    /// The manager was asked to shut down; the signal was forwarded to the
+    /// child, which has since exited.
+    Shutdown(ExitStatus),
+}
+
+/// Listens for the signals that should make the manager shut down.
+#[derive(Debug)]
+pub struct ShutdownSignals {
+    terminate: SignalStream,
+    interrupt: SignalStream,
+}
+
+impl ShutdownSignals {
+    pub fn new() -> Result<Self> {
+        Ok(ShutdownSignals {
+            terminate: unix_signal(SignalKind::terminate())
+                .context("Couldn't install the SIGTERM handler")?,
+            interrupt: unix_signal(SignalKind::interrupt())
+                .context("Couldn't install the SIGINT handler")?,
+        })
+    }
+
+    /// Waits for SIGTERM or SIGINT, returning which one arrived.
+    pub async fn recv(&mut self) -> Signal {
+        tokio::select! {
+            _ = self.terminate.recv() => Signal::SIGTERM,
+            _ = self.interrupt.recv() => Signal::SIGINT,
+        }
+    }
+}
+
+/// Waits for `child` to exit, terminating it if it runs longer than
+/// `timeout` or if the manager receives a shutdown signal in the meantime.
+pub async fn supervise(
+    mut child: Child,
+    timeout: Duration,
+    signals: &mut ShutdownSignals,
+) -> Result<Outcome> {
+    tokio::select! {
+        status = child.wait() => Ok(Outcome::Exited(status?)),
+        _ = time::sleep(timeout) => {
+            warn!("distance-db-populator ran for too long; terminating it");
+            let status = terminate(&mut child, Signal::SIGTERM).await?;
+            Ok(Outcome::TimedOut(status))
+        }
+        sig = signals.recv() => {
+            info!("Received {sig}; forwarding it to distance-db-populator");
+            let status = terminate(&mut child, sig).await?;
+            Ok(Outcome::Shutdown(status))
+        }
+    }
+}
+
+/// Sends `sig` to `child` and waits for it to exit, killing it if it doesn't
+/// exit within [`TERMINATION_GRACE_PERIOD`].
+async fn terminate(child: &mut Child, sig: Signal) -> Result<ExitStatus> {
+    if let Some(pid) = child.id() {
+        if let Err(e) = signal::kill(Pid::from_raw(pid as i32), sig) {
+            warn!("Couldn't send {sig} to distance-db-populator: {e}");
+        }
+
+        if let Ok(status) = time::timeout(TERMINATION_GRACE_PERIOD, child.wait()).await {
+            return Ok(status?);
+        }
+
+        warn!(
+            "distance-db-populator didn't exit within {} seconds; killing it",
+            TERMINATION_GRACE_PERIOD.as_secs()
+        );
+    }
+
+    child
+        .kill()
+        .await
+        .context("Couldn't kill the distance-db-populator process")?;
+
+    Ok(child.wait().await?)
+}
@@ -0,0 +1,81 @@
+use crate::child::{Outcome, ShutdownSignals};
+use crate::runs::PopulatorArgs;
+use anyhow::{Context, Result};
+use clap::Parser;
+use distance_db_core::populate::{self, Args};
+use distance_db_core::report::RunReport;
+use log::{error, info, warn};
+use nix::sys::signal::Signal;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::time::Duration;
+use std::{fs, io, iter};
+use tokio::time;
+
+/// How an in-process population ended.
+#[derive(Debug)]
+enum Ended {
+    Finished(Result<()>),
+    TimedOut,
+    Signal(Signal),
+}
+
+/// Runs a population in the manager's own process, as
+/// `./distance-db-populator` would run with `args`, writing its report to
+/// `report_path`.
+///
+/// The outcome is described as if the run had been a child process: a
+/// finished run exits with the populator's exit code, while a run that times
+/// out or is interrupted by a shutdown signal is dropped, rolling back its
+/// transaction, and reported as killed by SIGTERM or that signal.
+pub async fn run(
+    report_path: &Path,
+    args: &PopulatorArgs,
+    timeout: Duration,
+    signals: &mut ShutdownSignals,
+) -> Result<Outcome> {
+    info!("Starting an in-process population");
+    if let Err(e) = fs::remove_file(report_path)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        warn!("Couldn't remove stale populator report: {e}");
+    }
+
+    let args =
+        Args::try_parse_from(iter::once("distance-db-populator").chain(args.to_command_line()))
+            .context("Invalid populator arguments")?;
+
+    let mut report = RunReport::new();
+    let ended = tokio::select! {
+        result = populate::run(&args, &mut report) => Ended::Finished(result),
+        _ = time::sleep(timeout) => Ended::TimedOut,
+        sig = signals.recv() => Ended::Signal(sig),
+    };
+
+    match ended {
+        Ended::Finished(result) => {
+            if let Err(e) = &result {
+                error!("Population failed: {e:#}");
+            }
+            let exit_code = report.finish(&result);
+            if let Err(e) = report.write(report_path) {
+                warn!("{e:#}");
+            }
+
+            Ok(Outcome::Exited(ExitStatus::from_raw(
+                i32::from(exit_code) << 8,
+            )))
+        }
+        Ended::TimedOut => {
+            warn!("The in-process population ran for too long; cancelling it");
+            Ok(Outcome::TimedOut(ExitStatus::from_raw(
+                Signal::SIGTERM as i32,
+            )))
+        }
+        Ended::Signal(sig) => {
+            info!("Received {sig}; cancelling the in-process population");
+            Ok(Outcome::Shutdown(ExitStatus::from_raw(sig as i32)))
+        }
+    }
+}
@@ -5,17 +5,42 @@
     unused_qualifications
 )]
 
+use crate::admin::AdminState;
+use crate::child::{Outcome, ShutdownSignals};
+use crate::metrics::Metrics;
+use crate::notify::Notifier;
+use crate::output::RunLogs;
+use crate::runs::{PopulatorArgs, RunStatus, Runs, Trigger};
+use crate::schedule::{Backoff, Scheduler};
 use anyhow::{Context, Error, Result, format_err};
-use futures::pin_mut;
+use chrono::Utc;
+use distance_db_core::report::{RunReport, RunStatus as ReportStatus};
 use log::{error, info, warn};
-use std::fmt::Display;
-use std::process::ExitStatus;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::{env, process};
-use tokio::process::Command;
+use std::{env, fs, io, process};
+use tokio::process::{Child, Command};
+use tokio::sync::{mpsc, watch};
 use tokio::time;
 
+mod admin;
+mod child;
+mod in_process;
+mod metrics;
+mod notify;
+mod output;
+mod runs;
+mod schedule;
+
 const MAX_UPDATE_DURATION: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_METRICS_ADDRESS: &str = "0.0.0.0:9464";
+const DEFAULT_ADMIN_ADDRESS: &str = "127.0.0.1:9465";
+const DEFAULT_JOBS_PATH: &str = "/data/jobs.toml";
+const DEFAULT_SCHEDULE_STATE_PATH: &str = "/data/schedule-state.json";
+const MAX_SCHEDULER_SLEEP: Duration = Duration::from_secs(60);
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
@@ -38,11 +63,17 @@ async fn main() {
         }
     };
 
-    let result = run(healthchecks_url.as_deref()).await;
+    let result = async {
+        let notifier = Notifier::from_env(healthchecks_url.clone())?;
+        let metrics = start_metrics_server()?;
+        let control = start_admin_server()?;
+        run(&notifier, &metrics, control).await
+    }
+    .await;
 
     if let Err(e) = result {
         if let Some(url) = healthchecks_url {
-            healthchecks_send_fail_signal(&url, &format!("error: {e}"))
+            notify::healthchecks_send_fail_signal(&url, &format!("error: {e}"))
                 .await
                 .expect("Couldn't send healthchecks fail signal");
         }
@@ -61,73 +92,331 @@ fn print_error<E: Into<Error>>(e: E) {
     }
 }
 
-async fn run(healthchecks_url: Option<&str>) -> Result<()> {
+fn start_metrics_server() -> Result<Arc<Metrics>> {
+    let address: SocketAddr = env::var("METRICS_ADDRESS")
+        .unwrap_or_else(|_| DEFAULT_METRICS_ADDRESS.to_owned())
+        .parse()
+        .context("Invalid METRICS_ADDRESS environment variable")?;
+
+    let metrics = Arc::new(Metrics::new()?);
+    let server_metrics = Arc::clone(&metrics);
+    tokio::spawn(async move {
+        if let Err(e) = metrics::serve(address, server_metrics).await {
+            print_error(e);
+        }
+    });
+    info!("Serving metrics on http://{address}/metrics");
+
+    Ok(metrics)
+}
+
+/// How the admin API steers the run loop.
+#[derive(Debug)]
+struct Control {
+    runs: Arc<Runs>,
+    triggers: mpsc::Receiver<PopulatorArgs>,
+    paused: watch::Receiver<bool>,
+}
+
+fn start_admin_server() -> Result<Control> {
+    let runs = Arc::new(Runs::new());
+    let (triggers_tx, triggers) = mpsc::channel(1);
+    let (paused_tx, paused) = watch::channel(false);
+
+    match env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => {
+            let address: SocketAddr = env::var("ADMIN_ADDRESS")
+                .unwrap_or_else(|_| DEFAULT_ADMIN_ADDRESS.to_owned())
+                .parse()
+                .context("Invalid ADMIN_ADDRESS environment variable")?;
+
+            let state = AdminState {
+                token: token.into(),
+                runs: Arc::clone(&runs),
+                triggers: triggers_tx,
+                paused: Arc::new(paused_tx),
+            };
+            tokio::spawn(async move {
+                if let Err(e) = admin::serve(address, state).await {
+                    print_error(e);
+                }
+            });
+            info!("Serving the admin API on http://{address}");
+        }
+        _ => info!("Environment variable ADMIN_TOKEN is not set; the admin API is disabled"),
+    }
+
+    Ok(Control {
+        runs,
+        triggers,
+        paused,
+    })
+}
+
+/// How a run failed.
+#[derive(Debug)]
+struct RunFailure {
+    description: String,
+    /// Whether retrying is unlikely to help, e.g. after a configuration error.
+    needs_attention: bool,
+}
+
+impl RunFailure {
+    fn new(description: String) -> Self {
+        RunFailure {
+            description,
+            needs_attention: false,
+        }
+    }
+}
+
+/// A run that is about to start.
+#[derive(Debug)]
+struct NextRun {
+    trigger: Trigger,
+    job: Option<String>,
+    args: PopulatorArgs,
+    timeout: Duration,
+}
+
+async fn run(notifier: &Notifier, metrics: &Metrics, mut control: Control) -> Result<()> {
     let min_minutes_between_updates = env::var("MIN_MINUTES_BETWEEN_UPDATES")
         .map_err(Error::from)
         .and_then(|x| Ok(x.parse()?))
         .unwrap_or(60);
+    let jobs_path =
+        env::var_os("JOBS_PATH").map_or_else(|| DEFAULT_JOBS_PATH.into(), PathBuf::from);
+    let schedule_state_path = env::var_os("SCHEDULE_STATE_PATH")
+        .map_or_else(|| DEFAULT_SCHEDULE_STATE_PATH.into(), PathBuf::from);
+    let report_path = env::temp_dir().join("distance-db-populator-report.json");
+    let in_process = env::var("POPULATOR_IN_PROCESS").is_ok_and(|x| x == "1" || x == "true");
+
+    let jobs = schedule::load_jobs(&jobs_path, min_minutes_between_updates)?;
+    for job in &jobs {
+        info!("Loaded job `{}`: {}", job.name, job.schedule);
+    }
+    let mut scheduler = Scheduler::new(jobs, Backoff::from_env()?, schedule_state_path);
+    let run_logs = RunLogs::from_env();
+    metrics.set_consecutive_failures(scheduler.consecutive_failures());
+    let mut signals = ShutdownSignals::new()?;
 
     loop {
+        let Some(next) = wait_for_next_run(&scheduler, &mut control, &mut signals).await else {
+            return Ok(());
+        };
+
         let update_start_time = Instant::now();
-        let f = run_distance_db_populator();
-        pin_mut!(f);
-        match time::timeout(MAX_UPDATE_DURATION, f).await {
-            Ok(res) => {
-                match res {
-                    Ok(status) if status.success() => {
-                        if let Some(url) = healthchecks_url {
-                            healthchecks_send_ping(url).await.ok();
-                        }
+        metrics.run_started();
+        notifier.run_started().await;
+        let run_id = control
+            .runs
+            .start(next.trigger, next.job.clone(), next.args.clone());
+        if let Some(job) = &next.job {
+            info!("[run {run_id}] Running job `{job}`");
+            scheduler.job_started(job);
+        }
+
+        let (outcome, output_tail) = if in_process {
+            let outcome =
+                in_process::run(&report_path, &next.args, next.timeout, &mut signals).await;
+            (outcome, Vec::new())
+        } else {
+            match spawn_distance_db_populator(&report_path, &next.args) {
+                Ok(mut child) => {
+                    let output = output::capture(&mut child, run_id, &run_logs).await;
+                    if let Some(path) = &output.log_path {
+                        control.runs.set_log_file(path.clone());
+                    }
+                    let outcome = child::supervise(child, next.timeout, &mut signals).await;
+                    (outcome, output.finish().await)
+                }
+                Err(e) => (Err(e), Vec::new()),
+            }
+        };
+
+        // `None` if the run succeeded
+        let failure = match outcome {
+            Ok(Outcome::Shutdown(status)) => {
+                control.runs.finish(RunStatus::Interrupted, Some(status));
+                info!("distance-db-populator exited with {status}; shutting down");
+                return Ok(());
+            }
+            Ok(Outcome::TimedOut(status)) => {
+                control.runs.finish(RunStatus::TimedOut, Some(status));
+                metrics.run_timed_out(update_start_time.elapsed());
+                let description = format!(
+                    "ran for longer than {} minutes and was terminated ({status})",
+                    next.timeout.as_secs() / 60
+                );
+                print_error(format_err!("distance-db-populator {description}"));
+
+                Some(RunFailure::new(description))
+            }
+            Ok(Outcome::Exited(status)) => {
+                let report = match RunReport::read(&report_path) {
+                    Ok(report) => Some(report),
+                    Err(e) => {
+                        warn!("Couldn't read the populator report: {e:#}");
+                        None
+                    }
+                };
+                let report_status = report.as_ref().map(|r| r.status);
+                let run_status = if status.success() {
+                    RunStatus::Succeeded
+                } else if report_status == Some(ReportStatus::Partial) {
+                    RunStatus::Partial
+                } else {
+                    RunStatus::Failed
+                };
+                control.runs.finish(run_status, Some(status));
+                metrics.run_finished(Some(status), update_start_time.elapsed(), report.as_ref());
+
+                match (run_status, report) {
+                    (RunStatus::Partial, Some(report)) => {
+                        let skipped = report
+                            .skipped_leaderboards
+                            .iter()
+                            .filter(|&(_mode, &count)| count > 0)
+                            .map(|(mode, count)| format!("{count} {mode}"))
+                            .collect::<Vec<_>>();
+                        warn!(
+                            "distance-db-populator skipped some leaderboards: {}",
+                            skipped.join(", ")
+                        );
+                        None
                     }
-                    _ => {}
+                    (RunStatus::Failed, report) => {
+                        // The populator's error message already names the
+                        // kind of failure, if there is one.
+                        let failure_kind = report.as_ref().and_then(|r| r.failure);
+                        let description = match (report.and_then(|r| r.error), failure_kind) {
+                            (Some(error), _) => format!("failed with {status}: {error}"),
+                            (None, Some(kind)) => format!("failed with {status} ({kind})"),
+                            (None, None) => format!("failed with {status}"),
+                        };
+
+                        Some(RunFailure {
+                            description,
+                            needs_attention: failure_kind.is_some_and(|k| k.needs_attention()),
+                        })
+                    }
+                    _ => None,
                 }
+            }
+            Err(e) => {
+                control.runs.finish(RunStatus::Failed, None);
+                metrics.run_finished(None, update_start_time.elapsed(), None);
+                let description = format!("couldn't be run: {e:#}");
+                print_error(e);
+
+                Some(RunFailure::new(description))
+            }
+        };
 
-                time::sleep(
-                    Duration::from_secs(60 * min_minutes_between_updates)
-                        .checked_sub(update_start_time.elapsed())
-                        .unwrap_or_default(),
-                )
-                .await;
+        match failure {
+            None => {
+                let previous_failures = scheduler.consecutive_failures();
+                scheduler.run_succeeded();
+                notifier.run_succeeded(previous_failures).await;
             }
-            Err(_) => {
-                print_error(format_err!("distance-db-populator ran for too long"));
+            Some(failure) => {
+                let consecutive_failures = scheduler.run_failed(failure.needs_attention);
+                notifier
+                    .run_failed(
+                        &failure.description,
+                        &output_tail,
+                        consecutive_failures,
+                        failure.needs_attention,
+                    )
+                    .await;
+                if let Some(until) = scheduler.backoff_until() {
+                    warn!(
+                        "{consecutive_failures} consecutive failed run(s); holding off scheduled runs until {until}"
+                    );
+                }
             }
         }
+        metrics.set_consecutive_failures(scheduler.consecutive_failures());
+
+        if let Some(job) = &next.job {
+            scheduler.job_finished(job);
+        }
     }
 }
 
-async fn run_distance_db_populator() -> Result<ExitStatus> {
-    info!("Starting distance-db-populator");
-    let mut child = Command::new("./distance-db-populator")
-        .spawn()
-        .context("Couldn't spawn the distance-db-populator process")?;
+/// Waits until a scheduled job is due or a run is requested through the admin
+/// API. Returns `None` if the manager should shut down instead. If no job
+/// will ever be due again, only runs requested through the admin API are
+/// waited for.
+async fn wait_for_next_run(
+    scheduler: &Scheduler,
+    control: &mut Control,
+    signals: &mut ShutdownSignals,
+) -> Option<NextRun> {
+    let mut warned_nothing_scheduled = false;
+    loop {
+        let paused = *control.paused.borrow_and_update();
+        let now = Utc::now();
+        let next_due = scheduler.next_due(now);
+        if let Some((job, due)) = next_due
+            && !paused
+            && due <= now
+        {
+            return Some(NextRun {
+                trigger: Trigger::Schedule,
+                job: Some(job.name.clone()),
+                args: job.args.clone(),
+                timeout: job.timeout,
+            });
+        }
+        if next_due.is_none() && !warned_nothing_scheduled {
+            warn!(
+                "No job is scheduled to run again; waiting for runs requested through the admin API"
+            );
+            warned_nothing_scheduled = true;
+        }
 
-    Ok(child.wait().await?)
+        // Wake up regularly instead of sleeping until `due`, so that changes
+        // to the wall clock can't delay a job by much.
+        let sleep_duration = next_due.map(|(_, due)| {
+            (due - now)
+                .to_std()
+                .unwrap_or_default()
+                .min(MAX_SCHEDULER_SLEEP)
+        });
+        tokio::select! {
+            _ = time::sleep(sleep_duration.unwrap_or_default()), if !paused && sleep_duration.is_some() => {}
+            Some(args) = control.triggers.recv() => {
+                return Some(NextRun {
+                    trigger: Trigger::Api,
+                    job: None,
+                    args,
+                    timeout: MAX_UPDATE_DURATION,
+                });
+            }
+            Ok(()) = control.paused.changed() => {}
+            sig = signals.recv() => {
+                info!("Received {sig}; shutting down");
+                return None;
+            }
+        }
+    }
 }
 
-async fn healthchecks_send_ping(healthchecks_url: &str) -> Result<()> {
-    let err_msg = "error sending fail signal";
-
-    reqwest::get(healthchecks_url)
-        .await
-        .context(err_msg)?
-        .error_for_status()
-        .context(err_msg)?;
-
-    Ok(())
-}
+fn spawn_distance_db_populator(report_path: &Path, args: &PopulatorArgs) -> Result<Child> {
+    info!("Starting distance-db-populator");
+    if let Err(e) = fs::remove_file(report_path)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        warn!("Couldn't remove stale populator report: {e}");
+    }
 
-async fn healthchecks_send_fail_signal(
-    healthchecks_url: &str,
-    error: impl Display,
-) -> Result<(), Error> {
-    let client = reqwest::Client::new();
-    client
-        .post(format!("{healthchecks_url}/fail"))
-        .body(format!("[manager] error: {error}"))
-        .send()
-        .await
-        .map_err(|e| format_err!("Error sending fail signal: {}", e))?;
-
-    Ok(())
+    Command::new("./distance-db-populator")
+        .args(args.to_command_line())
+        .env("POPULATOR_REPORT_PATH", report_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Couldn't spawn the distance-db-populator process")
 }
@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+use axum::Router;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use distance_db_core::report::{RunReport, RunStatus as ReportStatus};
+use prometheus::{
+    Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::net::SocketAddr;
+use std::process::ExitStatus;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+
+/// Prometheus metrics about populator runs, served in the text exposition
+/// format on `/metrics`.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    runs: IntCounter,
+    successes: IntCounter,
+    failures: IntCounter,
+    failure_kinds: IntCounterVec,
+    partial_runs: IntCounter,
+    timeouts: IntCounter,
+    consecutive_failures: IntGauge,
+    last_exit_status: IntGauge,
+    run_duration: Histogram,
+    last_success_timestamp: Gauge,
+    seconds_since_last_success: Gauge,
+    phase_duration: GaugeVec,
+    phase_items: GaugeVec,
+    skipped_leaderboards: GaugeVec,
+    /// When the manager started, as a Unix timestamp, to count from until
+    /// the first success.
+    started_at: f64,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("distance_db".to_owned()), None)?;
+
+        let runs = IntCounter::new("populator_runs_total", "Number of populator runs started")?;
+        let successes = IntCounter::new(
+            "populator_run_successes_total",
+            "Number of populator runs that exited successfully",
+        )?;
+        let failures = IntCounter::new(
+            "populator_run_failures_total",
+            "Number of populator runs that failed, including timeouts",
+        )?;
+        let failure_kinds = IntCounterVec::new(
+            Opts::new(
+                "populator_run_failure_kinds_total",
+                "Number of failed populator runs by the kind of failure they reported",
+            ),
+            &["kind"],
+        )?;
+        let partial_runs = IntCounter::new(
+            "populator_run_partial_total",
+            "Number of successful populator runs that skipped some leaderboards",
+        )?;
+        let timeouts = IntCounter::new(
+            "populator_run_timeouts_total",
+            "Number of populator runs that exceeded the maximum update duration",
+        )?;
+        let consecutive_failures = IntGauge::new(
+            "populator_consecutive_failures",
+            "Number of populator runs that have failed in a row",
+        )?;
+        let last_exit_status = IntGauge::new(
+            "populator_last_exit_status",
+            "Exit code of the last populator run; -1 if it was killed or couldn't be started",
+        )?;
+        let run_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "populator_run_duration_seconds",
+                "Wall-clock duration of populator runs",
+            )
+            .buckets(vec![
+                60.0, 120.0, 300.0, 600.0, 900.0, 1200.0, 1800.0, 2700.0, 3600.0,
+            ]),
+        )?;
+        let last_success_timestamp = Gauge::new(
+            "populator_last_success_timestamp_seconds",
+            "Unix timestamp of the last successful populator run, or 0 if there hasn't been one",
+        )?;
+        let seconds_since_last_success = Gauge::new(
+            "populator_seconds_since_last_success",
+            "Seconds since the last successful populator run, or since the manager started",
+        )?;
+        let phase_duration = GaugeVec::new(
+            Opts::new(
+                "populator_phase_duration_seconds",
+                "Duration of each phase of the last successful populator run",
+            ),
+            &["phase"],
+        )?;
+        let phase_items = GaugeVec::new(
+            Opts::new(
+                "populator_phase_items",
+                "Number of items processed in each phase of the last successful populator run",
+            ),
+            &["phase"],
+        )?;
+        let skipped_leaderboards = GaugeVec::new(
+            Opts::new(
+                "populator_skipped_leaderboards",
+                "Number of leaderboards of each mode the last successful populator run skipped",
+            ),
+            &["mode"],
+        )?;
+
+        registry.register(Box::new(runs.clone()))?;
+        registry.register(Box::new(successes.clone()))?;
+        registry.register(Box::new(failures.clone()))?;
+        registry.register(Box::new(failure_kinds.clone()))?;
+        registry.register(Box::new(partial_runs.clone()))?;
+        registry.register(Box::new(timeouts.clone()))?;
+        registry.register(Box::new(consecutive_failures.clone()))?;
+        registry.register(Box::new(last_exit_status.clone()))?;
+        registry.register(Box::new(run_duration.clone()))?;
+        registry.register(Box::new(last_success_timestamp.clone()))?;
+        registry.register(Box::new(seconds_since_last_success.clone()))?;
+        registry.register(Box::new(phase_duration.clone()))?;
+        registry.register(Box::new(phase_items.clone()))?;
+        registry.register(Box::new(skipped_leaderboards.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            runs,
+            successes,
+            failures,
+            failure_kinds,
+            partial_runs,
+            timeouts,
+            consecutive_failures,
+            last_exit_status,
+            run_duration,
+            last_success_timestamp,
+            seconds_since_last_success,
+            phase_duration,
+            phase_items,
+            skipped_leaderboards,
+            started_at: unix_now(),
+        })
+    }
+
+    pub fn run_started(&self) {
+        self.runs.inc();
+    }
+
+    /// A run counts as successful if it exited successfully or reported a
+    /// partial success.
+    pub fn run_finished(
+        &self,
+        status: Option<ExitStatus>,
+        duration: Duration,
+        report: Option<&RunReport>,
+    ) {
+        self.run_duration.observe(duration.as_secs_f64());
+        self.last_exit_status
+            .set(status.and_then(|s| s.code()).map_or(-1, i64::from));
+
+        let partial = report.is_some_and(|r| r.status == ReportStatus::Partial);
+        if !(status.is_some_and(|s| s.success()) || partial) {
+            self.failures.inc();
+            if let Some(kind) = report.and_then(|r| r.failure) {
+                self.failure_kinds.with_label_values(&[kind.name()]).inc();
+            }
+            return;
+        }
+
+        self.successes.inc();
+        self.last_success_timestamp.set(unix_now());
+        if partial {
+            self.partial_runs.inc();
+        }
+
+        if let Some(report) = report {
+            self.phase_duration.reset();
+            self.phase_items.reset();
+            for phase in &report.phases {
+                self.phase_duration
+                    .with_label_values(&[&phase.name])
+                    .set(phase.duration_secs);
+                self.phase_items
+                    .with_label_values(&[&phase.name])
+                    .set(phase.items as f64);
+            }
+
+            self.skipped_leaderboards.reset();
+            for (mode, &count) in &report.skipped_leaderboards {
+                self.skipped_leaderboards
+                    .with_label_values(&[mode])
+                    .set(count as f64);
+            }
+        }
+    }
+
+    pub fn set_consecutive_failures(&self, n: u32) {
+        self.consecutive_failures.set(i64::from(n));
+    }
+
+    pub fn run_timed_out(&self, duration: Duration) {
+        self.timeouts.inc();
+        self.run_finished(None, duration, None);
+    }
+
+    fn render(&self) -> Result<String> {
+        let last_success = match self.last_success_timestamp.get() {
+            timestamp if timestamp > 0.0 => timestamp,
+            _ => self.started_at,
+        };
+        self.seconds_since_last_success
+            .set(unix_now() - last_success);
+
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+/// Serves `/metrics` on `address` until the process exits.
+pub async fn serve(address: SocketAddr, metrics: Arc<Metrics>) -> Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(metrics);
+
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Couldn't bind the metrics server to {address}"))?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn metrics_handler(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    match metrics.render() {
+        Ok(body) => (
+            [(
+                header::CONTENT_TYPE,
+                TextEncoder::new().format_type().to_owned(),
+            )],
+            body,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("error rendering metrics: {e}"),
+        )
+            .into_response(),
+    }
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
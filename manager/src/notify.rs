@@ -0,0 +1,176 @@
+use anyhow::{Context, Error, Result, format_err};
+use log::warn;
+use serde_json::json;
+use std::env;
+use std::fmt::Display;
+
+const DEFAULT_ESCALATE_AFTER_FAILURES: u32 = 3;
+
+/// Reports run outcomes to healthchecks.io and, after repeated failures, to an
+/// escalation webhook.
+#[derive(Debug)]
+pub struct Notifier {
+    client: reqwest::Client,
+    healthchecks_url: Option<String>,
+    escalation: Option<Escalation>,
+}
+
+#[derive(Debug)]
+struct Escalation {
+    webhook_url: String,
+    after_failures: u32,
+}
+
+impl Notifier {
+    pub fn from_env(healthchecks_url: Option<String>) -> Result<Self> {
+        let escalation = match env::var("ESCALATION_WEBHOOK_URL") {
+            Ok(webhook_url) => {
+                let after_failures = match env::var("ESCALATE_AFTER_FAILURES") {
+                    Ok(x) => x
+                        .parse()
+                        .context("Invalid ESCALATE_AFTER_FAILURES environment variable")?,
+                    Err(_) => DEFAULT_ESCALATE_AFTER_FAILURES,
+                };
+
+                Some(Escalation {
+                    webhook_url,
+                    after_failures: after_failures.max(1),
+                })
+            }
+            Err(_) => None,
+        };
+
+        Ok(Notifier {
+            client: reqwest::Client::new(),
+            healthchecks_url,
+            escalation,
+        })
+    }
+
+    /// Sends the healthchecks start signal, so run durations are tracked.
+    pub async fn run_started(&self) {
+        if let Some(url) = &self.healthchecks_url {
+            let result = self
+                .client
+                .post(format!("{url}/start"))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = result {
+                warn!("Couldn't send healthchecks start signal: {e}");
+            }
+        }
+    }
+
+    /// `previous_failures` is how many runs had failed in a row before this
+    /// one.
+    pub async fn run_succeeded(&self, previous_failures: u32) {
+        if let Some(url) = &self.healthchecks_url
+            && let Err(e) = healthchecks_send_ping(url).await
+        {
+            warn!("{e}");
+        }
+
+        if let Some(escalation) = &self.escalation
+            && previous_failures >= escalation.after_failures
+        {
+            self.escalate(
+                escalation,
+                &format!(
+                    "distance-db-populator succeeded again after {previous_failures} consecutive failures"
+                ),
+            )
+            .await;
+        }
+    }
+
+    /// `description` says how the run failed, e.g. which exit code it
+    /// returned. `consecutive_failures` includes this run.
+    ///
+    /// Failures that need attention, such as configuration errors, are
+    /// escalated without waiting for the failure threshold.
+    pub async fn run_failed(
+        &self,
+        description: &str,
+        output_tail: &[String],
+        consecutive_failures: u32,
+        needs_attention: bool,
+    ) {
+        let mut body = format!("[populator] {description}");
+        if !output_tail.is_empty() {
+            body.push_str("\n\nLast lines of output:\n");
+            body.push_str(&output_tail.join("\n"));
+        }
+
+        if let Some(url) = &self.healthchecks_url {
+            let result = self
+                .client
+                .post(format!("{url}/fail"))
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = result {
+                warn!("Couldn't send healthchecks fail signal: {e}");
+            }
+        }
+
+        if let Some(escalation) = &self.escalation {
+            if consecutive_failures == escalation.after_failures {
+                self.escalate(
+                    escalation,
+                    &format!(
+                        "distance-db-populator failed {consecutive_failures} times in a row\n\n{body}"
+                    ),
+                )
+                .await;
+            } else if needs_attention && consecutive_failures < escalation.after_failures {
+                self.escalate(
+                    escalation,
+                    &format!("distance-db-populator failed and needs attention\n\n{body}"),
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn escalate(&self, escalation: &Escalation, text: &str) {
+        let result = self
+            .client
+            .post(&escalation.webhook_url)
+            .json(&json!({ "text": text }))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            warn!("Couldn't send escalation webhook: {e}");
+        }
+    }
+}
+
+async fn healthchecks_send_ping(healthchecks_url: &str) -> Result<()> {
+    let err_msg = "error sending success signal";
+
+    reqwest::get(healthchecks_url)
+        .await
+        .context(err_msg)?
+        .error_for_status()
+        .context(err_msg)?;
+
+    Ok(())
+}
+
+pub async fn healthchecks_send_fail_signal(
+    healthchecks_url: &str,
+    error: impl Display,
+) -> Result<(), Error> {
+    let client = reqwest::Client::new();
+    client
+        .post(format!("{healthchecks_url}/fail"))
+        .body(format!("[manager] error: {error}"))
+        .send()
+        .await
+        .map_err(|e| format_err!("Error sending fail signal: {}", e))?;
+
+    Ok(())
+}
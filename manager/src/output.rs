@@ -0,0 +1,197 @@
+use chrono::{SecondsFormat, Utc};
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, io};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, BufWriter};
+use tokio::process::Child;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+const DEFAULT_LOG_DIR: &str = "/data/logs";
+const DEFAULT_LOGS_TO_KEEP: usize = 50;
+const DEFAULT_TAIL_LINES: usize = 50;
+
+/// Where per-run log files are kept, and how many of them.
+#[derive(Debug, Clone)]
+pub struct RunLogs {
+    dir: PathBuf,
+    keep: usize,
+    tail_lines: usize,
+}
+
+impl RunLogs {
+    pub fn from_env() -> Self {
+        let parse_var = |name: &str, default: usize| {
+            env::var(name)
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(default)
+        };
+
+        RunLogs {
+            dir: env::var_os("RUN_LOG_DIR").map_or_else(|| DEFAULT_LOG_DIR.into(), PathBuf::from),
+            keep: parse_var("RUN_LOGS_TO_KEEP", DEFAULT_LOGS_TO_KEEP).max(1),
+            tail_lines: parse_var("FAILURE_REPORT_LINES", DEFAULT_TAIL_LINES),
+        }
+    }
+
+    /// Creates the log file for a new run, deleting the oldest log files so
+    /// that at most `keep` remain.
+    async fn create(&self, run_id: u64) -> Option<(PathBuf, File)> {
+        let result = async {
+            fs::create_dir_all(&self.dir).await?;
+            self.prune().await?;
+
+            let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+            let path = self.dir.join(format!("run-{timestamp}-{run_id}.log"));
+            let file = File::create(&path).await?;
+
+            Ok::<_, io::Error>((path, file))
+        }
+        .await;
+
+        match result {
+            Ok(x) => Some(x),
+            Err(e) => {
+                warn!(
+                    "Couldn't create a run log file in {}: {e}",
+                    self.dir.display()
+                );
+                None
+            }
+        }
+    }
+
+    async fn prune(&self) -> io::Result<()> {
+        let mut log_files = Vec::new();
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("run-") && name.ends_with(".log") {
+                log_files.push(entry.path());
+            }
+        }
+
+        // File names start with a timestamp, so they sort chronologically.
+        log_files.sort();
+        let excess = (log_files.len() + 1).saturating_sub(self.keep);
+        for path in &log_files[..excess] {
+            fs::remove_file(path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// The captured output of a populator run.
+#[derive(Debug)]
+pub struct RunOutput {
+    pub log_path: Option<PathBuf>,
+    collector: JoinHandle<VecDeque<String>>,
+}
+
+impl RunOutput {
+    /// Waits for the child's output to end, returning the last lines of it.
+    pub async fn finish(self) -> Vec<String> {
+        // A grandchild could keep the pipes open, so don't wait forever.
+        match time::timeout(Duration::from_secs(5), self.collector).await {
+            Ok(Ok(tail)) => tail.into(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Takes over the piped stdout and stderr of `child`. Each line is logged
+/// tagged with `run_id`, appended to the run's log file, and the last few
+/// lines are kept for failure reports.
+pub async fn capture(child: &mut Child, run_id: u64, logs: &RunLogs) -> RunOutput {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if let Some(stdout) = child.stdout.take() {
+        forward_lines(stdout, Stream::Stdout, tx.clone());
+    }
+    if let Some(stderr) = child.stderr.take() {
+        forward_lines(stderr, Stream::Stderr, tx);
+    }
+
+    let (log_path, file) = logs.create(run_id).await.unzip();
+    if let Some(path) = &log_path {
+        info!("[run {run_id}] Logging output to {}", path.display());
+    }
+
+    let tail_lines = logs.tail_lines;
+    let collector = tokio::spawn(async move {
+        let mut file = file.map(BufWriter::new);
+        let mut tail = VecDeque::with_capacity(tail_lines);
+
+        while let Some((stream, line)) = rx.recv().await {
+            match stream {
+                Stream::Stdout => info!(target: "populator", "[run {run_id}] {line}"),
+                Stream::Stderr => warn!(target: "populator", "[run {run_id}] {line}"),
+            }
+
+            if let Some(f) = &mut file {
+                let stream_name = match stream {
+                    Stream::Stdout => "stdout",
+                    Stream::Stderr => "stderr",
+                };
+                let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+                let record = format!("{timestamp} [{stream_name}] {line}\n");
+                if let Err(e) = f.write_all(record.as_bytes()).await {
+                    warn!("Couldn't write to the run log file: {e}");
+                    file = None;
+                }
+            }
+
+            if tail_lines > 0 {
+                if tail.len() == tail_lines {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+        }
+
+        if let Some(f) = &mut file {
+            f.flush().await.ok();
+        }
+
+        tail
+    });
+
+    RunOutput {
+        log_path,
+        collector,
+    }
+}
+
+fn forward_lines(
+    reader: impl AsyncRead + Unpin + Send + 'static,
+    stream: Stream,
+    tx: mpsc::UnboundedSender<(Stream, String)>,
+) {
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        let mut buf = Vec::new();
+        while let Ok(n) = reader.read_until(b'\n', &mut buf).await {
+            if n == 0 {
+                break;
+            }
+
+            let line = String::from_utf8_lossy(&buf).trim_end().to_owned();
+            buf.clear();
+            if tx.send((stream, line)).is_err() {
+                break;
+            }
+        }
+    });
+}
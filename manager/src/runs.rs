@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::Mutex;
+
+/// How many finished runs to remember.
+const HISTORY_LEN: usize = 50;
+
+/// Arguments to pass to the populator for a single run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PopulatorArgs {
+    /// Only refresh the leaderboards of these modes; empty means all modes.
+    #[serde(default)]
+    pub modes: Vec<Mode>,
+
+    /// Rewrite every leaderboard, even those whose stored hash matches.
+    #[serde(default)]
+    pub force_rebuild: bool,
+
+    /// Reuse the stored workshop levels instead of querying the workshop.
+    #[serde(default)]
+    pub skip_workshop_query: bool,
+
+    /// Only resolve the names of users that aren't in the database yet.
+    #[serde(default)]
+    pub only_new_names: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Sprint,
+    Challenge,
+    Stunt,
+}
+
+impl PopulatorArgs {
+    pub fn to_command_line(&self) -> Vec<&'static str> {
+        let mut args = Vec::new();
+        for mode in &self.modes {
+            args.push("--mode");
+            args.push(match mode {
+                Mode::Sprint => "sprint",
+                Mode::Challenge => "challenge",
+                Mode::Stunt => "stunt",
+            });
+        }
+        if self.force_rebuild {
+            args.push("--force-rebuild");
+        }
+        if self.skip_workshop_query {
+            args.push("--skip-workshop-query");
+        }
+        if self.only_new_names {
+            args.push("--only-new-names");
+        }
+
+        args
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    Schedule,
+    Api,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    /// Succeeded, but some leaderboards were skipped.
+    Partial,
+    Failed,
+    TimedOut,
+    Interrupted,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub id: u64,
+    pub trigger: Trigger,
+    /// The scheduled job this run belongs to, if any.
+    pub job: Option<String>,
+    pub args: PopulatorArgs,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub status: RunStatus,
+    pub exit_code: Option<i32>,
+    pub log_file: Option<PathBuf>,
+}
+
+/// The current run, if any, and the most recent finished runs.
+#[derive(Debug, Default)]
+pub struct Runs {
+    inner: Mutex<RunsInner>,
+}
+
+#[derive(Debug, Default)]
+struct RunsInner {
+    next_id: u64,
+    current: Option<RunRecord>,
+    history: VecDeque<RunRecord>,
+}
+
+impl Runs {
+    pub fn new() -> Self {
+        Runs::default()
+    }
+
+    pub fn start(&self, trigger: Trigger, job: Option<String>, args: PopulatorArgs) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.current = Some(RunRecord {
+            id,
+            trigger,
+            job,
+            args,
+            started_at: Utc::now(),
+            finished_at: None,
+            status: RunStatus::Running,
+            exit_code: None,
+            log_file: None,
+        });
+
+        id
+    }
+
+    pub fn set_log_file(&self, path: PathBuf) {
+        if let Some(run) = &mut self.inner.lock().unwrap().current {
+            run.log_file = Some(path);
+        }
+    }
+
+    pub fn finish(&self, status: RunStatus, exit_status: Option<ExitStatus>) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(mut run) = inner.current.take() {
+            run.finished_at = Some(Utc::now());
+            run.status = status;
+            run.exit_code = exit_status.and_then(|s| s.code());
+
+            if inner.history.len() == HISTORY_LEN {
+                inner.history.pop_back();
+            }
+            inner.history.push_front(run);
+        }
+    }
+
+    pub fn current(&self) -> Option<RunRecord> {
+        self.inner.lock().unwrap().current.clone()
+    }
+
+    /// Returns the current run followed by finished runs, newest first.
+    pub fn recent(&self) -> Vec<RunRecord> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .current
+            .iter()
+            .chain(&inner.history)
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,348 @@
+use crate::runs::PopulatorArgs;
+use anyhow::{Context, Error, Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use cron::Schedule;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use std::{env, fs, io};
+
+const DEFAULT_TIMEOUT_MINUTES: u64 = 60;
+const DEFAULT_BACKOFF_MINUTES: u64 = 15;
+const DEFAULT_MAX_BACKOFF_MINUTES: u64 = 6 * 60;
+
+/// A named kind of populator run, with its own schedule and timeout.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub name: String,
+    pub schedule: JobSchedule,
+    pub timeout: Duration,
+    pub args: PopulatorArgs,
+}
+
+#[derive(Debug, Clone)]
+pub enum JobSchedule {
+    Cron(Box<Schedule>),
+    /// Run this long after the previous run of the job started.
+    Interval(TimeDelta),
+}
+
+impl JobSchedule {
+    /// Returns when the job should run next, given when it last started.
+    ///
+    /// Cron jobs that have never run wait for their next occurrence, while
+    /// interval jobs that have never run are due immediately. A run that was
+    /// missed, e.g. while the manager was down, is due immediately; several
+    /// missed runs are only made up for once.
+    fn next_run(
+        &self,
+        last_started_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        match (self, last_started_at) {
+            (JobSchedule::Cron(schedule), Some(last)) => schedule.after(&last).next(),
+            (JobSchedule::Cron(schedule), None) => schedule.after(&now).next(),
+            (JobSchedule::Interval(interval), Some(last)) => Some(last + *interval),
+            (JobSchedule::Interval(_), None) => Some(now),
+        }
+    }
+}
+
+impl Display for JobSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobSchedule::Cron(schedule) => write!(f, "cron `{}`", schedule.source()),
+            JobSchedule::Interval(interval) => {
+                write!(f, "every {} minutes", interval.num_minutes())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobsFile {
+    jobs: Vec<JobConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobConfig {
+    name: String,
+    schedule: Option<String>,
+    every_minutes: Option<u64>,
+    timeout_minutes: Option<u64>,
+    #[serde(default)]
+    args: PopulatorArgs,
+}
+
+/// Loads the job definitions from the TOML file at `path`.
+///
+/// If the file doesn't exist, a single job named `default` is returned that
+/// does a full run every `default_interval_minutes` minutes.
+pub fn load_jobs(path: &Path, default_interval_minutes: u64) -> Result<Vec<Job>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(vec![Job {
+                name: "default".to_owned(),
+                schedule: JobSchedule::Interval(minutes(default_interval_minutes)?),
+                timeout: Duration::from_secs(60 * DEFAULT_TIMEOUT_MINUTES),
+                args: PopulatorArgs::default(),
+            }]);
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("Couldn't read {}", path.display()));
+        }
+    };
+
+    let file: JobsFile =
+        toml::from_str(&contents).with_context(|| format!("Couldn't parse {}", path.display()))?;
+    if file.jobs.is_empty() {
+        bail!("{} doesn't define any jobs", path.display());
+    }
+
+    let mut names = HashSet::new();
+    file.jobs
+        .into_iter()
+        .map(|config| {
+            if !names.insert(config.name.clone()) {
+                bail!("Job `{}` is defined more than once", config.name);
+            }
+
+            let schedule = match (config.schedule, config.every_minutes) {
+                (Some(expression), None) => JobSchedule::Cron(Box::new(
+                    parse_cron(&expression)
+                        .with_context(|| format!("Invalid schedule for job `{}`", config.name))?,
+                )),
+                (None, Some(every_minutes)) => JobSchedule::Interval(minutes(every_minutes)?),
+                _ => bail!(
+                    "Job `{}` must have exactly one of `schedule` and `every_minutes`",
+                    config.name
+                ),
+            };
+
+            Ok(Job {
+                name: config.name,
+                schedule,
+                timeout: Duration::from_secs(
+                    60 * config.timeout_minutes.unwrap_or(DEFAULT_TIMEOUT_MINUTES),
+                ),
+                args: config.args,
+            })
+        })
+        .collect()
+}
+
+/// Parses a cron expression. Standard five-field expressions are accepted,
+/// as well as expressions with a leading seconds field.
+fn parse_cron(expression: &str) -> Result<Schedule> {
+    let expression = if expression.split_whitespace().count() == 5 {
+        format!("0 {expression}")
+    } else {
+        expression.to_owned()
+    };
+
+    Ok(Schedule::from_str(&expression)?)
+}
+
+fn minutes(n: u64) -> Result<TimeDelta> {
+    if n == 0 {
+        bail!("Intervals must be at least one minute long");
+    }
+
+    TimeDelta::try_minutes(n.try_into()?).context("Interval is too long")
+}
+
+/// How long to hold off scheduled runs after consecutive failures.
+#[derive(Debug, Copy, Clone)]
+pub struct Backoff {
+    base: TimeDelta,
+    max: TimeDelta,
+}
+
+impl Backoff {
+    pub fn from_env() -> Result<Self> {
+        let minutes_from_env = |name: &str, default: u64| -> Result<TimeDelta> {
+            match env::var(name) {
+                Ok(x) => minutes(
+                    x.parse()
+                        .with_context(|| format!("Invalid {name} environment variable"))?,
+                ),
+                Err(_) => minutes(default),
+            }
+        };
+
+        Ok(Backoff {
+            base: minutes_from_env("FAILURE_BACKOFF_MINUTES", DEFAULT_BACKOFF_MINUTES)?,
+            max: minutes_from_env("MAX_FAILURE_BACKOFF_MINUTES", DEFAULT_MAX_BACKOFF_MINUTES)?,
+        })
+    }
+
+    /// Returns `base` after one failure, doubling with each further failure,
+    /// up to `max`.
+    fn delay(&self, consecutive_failures: u32) -> TimeDelta {
+        if consecutive_failures == 0 {
+            return TimeDelta::zero();
+        }
+
+        let factor = 2_i32.saturating_pow(consecutive_failures - 1);
+        self.base
+            .checked_mul(factor)
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ScheduleState {
+    jobs: BTreeMap<String, JobState>,
+    consecutive_failures: u32,
+    last_failed_at: Option<DateTime<Utc>>,
+    /// Whether the last run failed in a way that retrying is unlikely to fix.
+    last_failure_needs_attention: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct JobState {
+    last_started_at: Option<DateTime<Utc>>,
+    last_finished_at: Option<DateTime<Utc>>,
+}
+
+/// Decides which job runs next. When each job last ran, and how many runs
+/// have failed in a row, is persisted to a JSON file, so schedules survive
+/// manager restarts.
+#[derive(Debug)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+    backoff: Backoff,
+    state: ScheduleState,
+    state_path: PathBuf,
+}
+
+impl Scheduler {
+    pub fn new(jobs: Vec<Job>, backoff: Backoff, state_path: PathBuf) -> Self {
+        let state = match fs::read(&state_path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring invalid schedule state in {}: {e}",
+                    state_path.display()
+                );
+                ScheduleState::default()
+            }),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Couldn't read {}: {e}", state_path.display());
+                }
+                ScheduleState::default()
+            }
+        };
+
+        Scheduler {
+            jobs,
+            backoff,
+            state,
+            state_path,
+        }
+    }
+
+    /// Returns the job that is due next, and when. If several jobs are due at
+    /// the same time, the one defined first wins. After failed runs, no job is
+    /// due before the backoff delay has passed.
+    pub fn next_due(&self, now: DateTime<Utc>) -> Option<(&Job, DateTime<Utc>)> {
+        let not_before = self.backoff_until().unwrap_or(now).max(now);
+
+        self.jobs
+            .iter()
+            .filter_map(|job| {
+                let last_started_at = self
+                    .state
+                    .jobs
+                    .get(&job.name)
+                    .and_then(|state| state.last_started_at);
+                let next_run = job.schedule.next_run(last_started_at, now)?;
+
+                Some((job, next_run.max(not_before)))
+            })
+            .min_by_key(|&(_job, next_run)| next_run)
+    }
+
+    pub fn job_started(&mut self, name: &str) {
+        self.state
+            .jobs
+            .entry(name.to_owned())
+            .or_default()
+            .last_started_at = Some(Utc::now());
+        self.save();
+    }
+
+    pub fn job_finished(&mut self, name: &str) {
+        self.state
+            .jobs
+            .entry(name.to_owned())
+            .or_default()
+            .last_finished_at = Some(Utc::now());
+        self.save();
+    }
+
+    /// Returns when scheduled runs may resume after the last failure, if the
+    /// last run failed. Failures that need attention are backed off for the
+    /// maximum delay right away.
+    pub fn backoff_until(&self) -> Option<DateTime<Utc>> {
+        let last_failed_at = self.state.last_failed_at?;
+        if self.state.consecutive_failures == 0 {
+            return None;
+        }
+
+        let delay = if self.state.last_failure_needs_attention {
+            self.backoff.max
+        } else {
+            self.backoff.delay(self.state.consecutive_failures)
+        };
+
+        Some(last_failed_at + delay)
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.state.consecutive_failures
+    }
+
+    pub fn run_succeeded(&mut self) {
+        self.state.consecutive_failures = 0;
+        self.state.last_failed_at = None;
+        self.state.last_failure_needs_attention = false;
+        self.save();
+    }
+
+    /// Records a failed run, returning how many runs have failed in a row.
+    pub fn run_failed(&mut self, needs_attention: bool) -> u32 {
+        self.state.consecutive_failures += 1;
+        self.state.last_failed_at = Some(Utc::now());
+        self.state.last_failure_needs_attention = needs_attention;
+        self.save();
+
+        self.state.consecutive_failures
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_vec_pretty(&self.state)
+            .map_err(Error::from)
+            .and_then(|json| {
+                let tmp_path = self.state_path.with_extension("json.tmp");
+                fs::write(&tmp_path, json)?;
+                fs::rename(&tmp_path, &self.state_path)?;
+                Ok(())
+            });
+
+        if let Err(e) = result {
+            warn!(
+                "Couldn't save the schedule state to {}: {e}",
+                self.state_path.display()
+            );
+        }
+    }
+}
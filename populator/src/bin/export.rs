@@ -0,0 +1,135 @@
+#![warn(
+    rust_2018_idioms,
+    deprecated_in_future,
+    macro_use_extern_crate,
+    missing_debug_implementations,
+    unused_qualifications
+)]
+
+use anyhow::{Context, Error, format_err};
+use clap::{Parser, ValueEnum};
+use distance_db_core::anonymize::{self, Anonymizer, AuthorPolicy, NamePolicy};
+use distance_db_core::logging::{LogFormat, Service};
+use distance_db_core::parquet_export::{self, ParquetOptions};
+use distance_db_core::{db, dump, logging, static_api};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use tracing::{info, info_span};
+
+/// Spans of this binary are exported along with the library's.
+const SERVICE: Service = Service {
+    name: env!("CARGO_PKG_NAME"),
+    target: env!("CARGO_CRATE_NAME"),
+};
+
+/// Export the data stored in the Distance Database for offline analysis.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// The directory to write the exported files to.
+    dir: PathBuf,
+
+    /// The format of the exported files.
+    #[arg(long, value_enum, default_value_t = Format::Parquet)]
+    format: Format,
+
+    /// Write each mode's leaderboard entries to a partition of their own.
+    /// Parquet only.
+    #[arg(long)]
+    partition_by_mode: bool,
+
+    /// Pseudonymize players, replacing their Steam IDs with keyed hashes of
+    /// them. Dumps only.
+    #[arg(long, requires = "pseudonym_key")]
+    anonymize: bool,
+
+    /// The secret key pseudonyms are derived with, at least 16 bytes long.
+    /// Keep it the same across dumps for pseudonyms to stay the same.
+    #[arg(long, env = "PSEUDONYM_KEY", hide_env_values = true)]
+    pseudonym_key: Option<String>,
+
+    /// What to do with persona names in anonymized dumps.
+    #[arg(long, value_enum, default_value_t = NamePolicy::Redact)]
+    names: NamePolicy,
+
+    /// What to do with the authors of workshop levels in anonymized dumps.
+    #[arg(long, value_enum, default_value_t = AuthorPolicy::Pseudonymize)]
+    authors: AuthorPolicy,
+
+    /// A file listing the Steam IDs of authors who have opted in to keeping
+    /// their Steam ID and name in anonymized dumps, one per line.
+    #[arg(long, value_name = "FILE")]
+    opted_in_authors: Option<PathBuf>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Parquet files, one per table
+    Parquet,
+    /// CSV and JSON Lines files of each table, with a manifest of their row
+    /// counts and checksums
+    Dump,
+    /// A static JSON API of levels, leaderboards and players, rewriting only
+    /// the files that changed
+    StaticApi,
+}
+
+impl Args {
+    /// The anonymizer `--anonymize` asks for, if any.
+    fn anonymizer(&self) -> Result<Option<Anonymizer>, Error> {
+        if !self.anonymize {
+            return Ok(None);
+        }
+
+        let key = self.pseudonym_key.as_deref().unwrap_or_default();
+        let opted_in_authors = match &self.opted_in_authors {
+            Some(path) => anonymize::read_steam_ids(path)?,
+            None => HashSet::new(),
+        };
+
+        Anonymizer::new(key.as_bytes(), self.names, self.authors, opted_in_authors).map(Some)
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Error> {
+    color_backtrace::install();
+    dotenv::dotenv().ok();
+
+    let args = Args::parse();
+    if args.anonymize && args.format != Format::Dump {
+        return Err(format_err!("--anonymize is only supported for dumps"));
+    }
+    let anonymizer = args.anonymizer()?;
+    let _telemetry = logging::init(LogFormat::Text, SERVICE)?;
+
+    info!("Connecting to database");
+    let mut db = db::establish_connection().await?;
+
+    match args.format {
+        Format::Parquet => {
+            let data = db::load(&mut db)
+                .await
+                .context("error loading the stored data")?;
+            let options = ParquetOptions {
+                partition_by_mode: args.partition_by_mode,
+            };
+            info_span!("parquet_export")
+                .in_scope(|| parquet_export::write(&data, &args.dir, options))
+                .context("error exporting Parquet files")?;
+        }
+        Format::Dump => {
+            dump::write(&mut db, &args.dir, anonymizer.as_ref())
+                .await
+                .context("error dumping the database")?;
+        }
+        Format::StaticApi => {
+            static_api::write(&mut db, &args.dir)
+                .await
+                .context("error generating the static API")?;
+        }
+    }
+    info!(dir = %args.dir.display(), "Exported the stored data");
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+#![warn(
+    rust_2018_idioms,
+    deprecated_in_future,
+    macro_use_extern_crate,
+    missing_debug_implementations,
+    unused_qualifications
+)]
+
+use anyhow::{Context, Error, format_err};
+use clap::Parser;
+use distance_db_core::common::RunOptions;
+use distance_db_core::logging::{LogFormat, Service};
+use distance_db_core::report::RunReport;
+use distance_db_core::synthetic::{self, SyntheticConfig};
+use distance_db_core::{data_storing, db, lock, logging};
+use std::time::Duration;
+use tracing::{Instrument, info, info_span};
+
+/// Spans of this binary are exported along with the library's.
+const SERVICE: Service = Service {
+    name: env!("CARGO_PKG_NAME"),
+    target: env!("CARGO_CRATE_NAME"),
+};
+
+/// Fill a development database with made-up levels, players and leaderboards,
+/// so no Steam Web API key or DistanceSteamDataServer is needed.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// The number of official levels, at most the number in the game.
+    /// Defaults to all of them.
+    #[arg(long)]
+    official_levels: Option<usize>,
+
+    /// The number of workshop levels.
+    #[arg(long, default_value_t = SyntheticConfig::default().workshop_levels)]
+    workshop_levels: usize,
+
+    /// The number of players.
+    #[arg(long, default_value_t = SyntheticConfig::default().users)]
+    users: usize,
+
+    /// The exponent of the power law leaderboard sizes follow. Lower values
+    /// make large leaderboards more common.
+    #[arg(long, default_value_t = SyntheticConfig::default().size_exponent)]
+    size_exponent: f64,
+
+    /// The seed of the random number generator. The same seed and counts
+    /// always generate the same data.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Store the data even if the database already contains levels.
+    #[arg(long)]
+    overwrite: bool,
+}
+
+impl Args {
+    fn config(&self) -> SyntheticConfig {
+        SyntheticConfig {
+            official_levels: self.official_levels.unwrap_or(usize::MAX),
+            workshop_levels: self.workshop_levels,
+            users: self.users,
+            size_exponent: self.size_exponent,
+            seed: self.seed,
+        }
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Error> {
+    color_backtrace::install();
+    dotenv::dotenv().ok();
+
+    let args = Args::parse();
+    if args.size_exponent <= 0.0 {
+        return Err(format_err!("--size-exponent must be positive"));
+    }
+    let _telemetry = logging::init(LogFormat::Text, SERVICE)?;
+
+    info!("Connecting to database");
+    let mut db = db::establish_connection().await?;
+    lock::acquire(&db, Duration::ZERO).await?;
+
+    // Don't mix made-up data into a real database by accident
+    let stored_levels: i64 = db
+        .query_one("SELECT count(*) FROM levels", &[])
+        .await?
+        .get(0);
+    if stored_levels > 0 && !args.overwrite {
+        return Err(format_err!(
+            "the database already contains {stored_levels} levels; pass --overwrite to store the generated data anyway"
+        ));
+    }
+
+    let mut data = synthetic::generate(&args.config());
+    let entries: usize = data
+        .levels
+        .iter()
+        .map(|l| l.sprint_entries.len() + l.challenge_entries.len() + l.stunt_entries.len())
+        .sum();
+    info!(
+        levels = data.levels.len(),
+        users = data.users.len(),
+        entries,
+        "Generated data"
+    );
+
+    let options = RunOptions {
+        force_rebuild: true,
+        ..RunOptions::default()
+    };
+    data_storing::run(&mut db, &mut data, options, &mut RunReport::new())
+        .instrument(info_span!("data_storing"))
+        .await
+        .context("error storing data")?;
+    info!("Stored generated data");
+
+    Ok(())
+}
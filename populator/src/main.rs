@@ -6,114 +6,146 @@
     unused_qualifications
 )]
 
-use crate::common::DistanceData;
-use anyhow::{Context, Error, anyhow};
-use distance_steam_data_client::Client as GrpcClient;
-use futures::prelude::*;
-use std::env;
-use std::time::Instant;
+use anyhow::Error;
+use clap::{Parser, Subcommand};
+use distance_db_core::failure::FailureKind;
+use distance_db_core::logging::Service;
+use distance_db_core::populate::{self, Args};
+use distance_db_core::report::{RunReport, RunStatus};
+use distance_db_core::{db, logging, opt_out, sqlite_storage};
+use std::process::ExitCode;
+use tracing::{Instrument, error, info, info_span, warn};
+
+/// Spans of this binary are exported along with the library's.
+const SERVICE: Service = Service {
+    name: env!("CARGO_PKG_NAME"),
+    target: env!("CARGO_CRATE_NAME"),
+};
+
+/// Populate the Distance Database with data from Steam.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(flatten)]
+    args: Args,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage the players whose names are hidden
+    #[command(subcommand)]
+    OptOut(OptOutCommand),
+}
 
-mod common;
-mod data_collection;
-mod data_storing;
+#[derive(Debug, Subcommand)]
+enum OptOutCommand {
+    /// Hide the names of these players from the next run on. Run `purge` to
+    /// also hide the names already stored.
+    Add {
+        #[arg(required = true)]
+        steam_ids: Vec<u64>,
+    },
+    /// Show the names of these players again, once a run looks them up
+    Remove {
+        #[arg(required = true)]
+        steam_ids: Vec<u64>,
+    },
+    /// Print the Steam IDs whose names are hidden, one per line
+    List,
+    /// Replace the names already stored for everyone on the list, including
+    /// in the SQLite copy if there is one. Parquet exports, dumps and the
+    /// static API keep the old names until they're made again
+    Purge,
+}
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), Error> {
+async fn main() -> ExitCode {
     color_backtrace::install();
-    tracing_subscriber::fmt::init();
     dotenv::dotenv().ok();
 
-    let grpc_server_address = env::var("GRPC_SERVER_ADDRESS")
-        .context("The environment variable `GRPC_SERVER_ADDRESS` must be set.")?;
-
-    let distance_data = {
-        let steam_web_api_key = env::var("STEAM_WEB_API_KEY")
-            .expect("environment variable STEAM_WEB_API_KEY is not set");
-
-        let web_client = reqwest::Client::new();
-
-        println!("Connecting to Distance gRPC server...");
-        let grpc = GrpcClient::connect(&grpc_server_address).await?;
-        println!("Connected.");
-
-        println!("Starting data collection.");
-        let start_instant = Instant::now();
-        let data = data_collection::run(web_client, grpc, steam_web_api_key.clone())
+    let Cli { args, command } = Cli::parse();
+    if let Some(Command::OptOut(command)) = command {
+        let result = match logging::init(args.log_format, SERVICE) {
+            Ok(_telemetry) => manage_opt_outs(command, &args).await,
+            Err(e) => Err(e),
+        };
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:?}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let mut report = RunReport::new();
+
+    let result = match logging::init(args.log_format, SERVICE) {
+        Ok(_telemetry) => {
+            async {
+                let result = populate::run(&args, &mut report).await;
+                if let Err(e) = &result {
+                    error!("{e:?}");
+                }
+                result
+            }
+            .instrument(info_span!("populator_run"))
             .await
-            .context("error acquiring data")?;
-        let data_collection_time = Instant::now().duration_since(start_instant);
-        println!(
-            "Finished collecting data in {} seconds.",
-            data_collection_time.as_secs()
-        );
-
-        data
+        }
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            Err(e.context(FailureKind::Config))
+        }
     };
 
-    print_stats(&distance_data);
-
-    println!("Connecting to database...");
-    let mut db = establish_connection().await?;
-    println!("Connected to database.");
-
-    data_storing::run(&mut db, distance_data)
-        .await
-        .context("error storing data")?;
-
-    println!("Finished successfully.");
-
-    Ok(())
+    let exit_code = report.finish(&result);
+    match report.status {
+        RunStatus::Succeeded => info!("Finished successfully"),
+        RunStatus::Partial => warn!(exit_code, "Finished, but some leaderboards were skipped"),
+        RunStatus::Failed => error!(exit_code, "Run failed"),
+    }
+    if let Err(e) = report.write_if_requested() {
+        error!("{e:#}");
+    }
+
+    ExitCode::from(exit_code)
 }
 
-async fn establish_connection() -> Result<tokio_postgres::Client, Error> {
-    let database_url =
-        env::var("DATABASE_URL").context("Environment variable DATABASE_URL is not set")?;
-
-    let (client, connection) =
-        tokio_postgres::connect(&database_url, tokio_postgres::NoTls).await?;
-
-    let connection = connection.map(|r| {
-        if let Err(e) = r {
-            eprintln!("{}", anyhow!("connection error: {}", e));
+async fn manage_opt_outs(command: OptOutCommand, args: &Args) -> Result<(), Error> {
+    let db = db::connect(args.database_url()?).await?;
+    opt_out::create_table(&db).await?;
+    match command {
+        OptOutCommand::Add { steam_ids } => {
+            let added = opt_out::add(&db, &steam_ids).await?;
+            info!(added, "Added players to the opt-out list");
         }
-    });
-    tokio::spawn(connection);
-
-    Ok(client)
-}
-
-fn print_stats(data: &DistanceData) {
-    let total_levels = data.levels.len();
-    let official_levels = data
-        .levels
-        .iter()
-        .filter(|level| level.workshop_level_details.is_none())
-        .count();
-    let workshop_levels = total_levels - official_levels;
-    println!(
-        "Total levels: {total_levels} (Official: {official_levels}, Workshop: {workshop_levels})"
-    );
-
-    let total_users = data.users.len();
-    println!("Total users: {total_users}");
+        OptOutCommand::Remove { steam_ids } => {
+            let removed = opt_out::remove(&db, &steam_ids).await?;
+            info!(removed, "Removed players from the opt-out list");
+        }
+        OptOutCommand::List => {
+            let mut steam_ids: Vec<_> = opt_out::list(&db).await?.into_iter().collect();
+            steam_ids.sort_unstable();
+            for steam_id in steam_ids {
+                println!("{steam_id}");
+            }
+        }
+        OptOutCommand::Purge => {
+            let purged = opt_out::purge(&db).await?;
+            info!(purged, "Hid the stored names of opted-out players");
+            if let Some(path) = &args.sqlite_copy {
+                let sqlite = sqlite_storage::open(path)?;
+                let purged = opt_out::purge_sqlite(&sqlite, &opt_out::list(&db).await?)?;
+                info!(
+                    purged,
+                    "Hid the names of opted-out players in the SQLite copy"
+                );
+            }
+        }
+    }
 
-    let sprint_entries: usize = data
-        .levels
-        .iter()
-        .map(|level| level.sprint_entries.len())
-        .sum();
-    let challenge_entries: usize = data
-        .levels
-        .iter()
-        .map(|level| level.challenge_entries.len())
-        .sum();
-    let stunt_entries: usize = data
-        .levels
-        .iter()
-        .map(|level| level.stunt_entries.len())
-        .sum();
-    let total_entries = sprint_entries + challenge_entries + stunt_entries;
-    println!(
-        "Total leaderboard entries: {total_entries} (Sprint: {sprint_entries}, Challenge: {challenge_entries}, Stunt: {stunt_entries})"
-    );
+    Ok(())
 }
@@ -0,0 +1,93 @@
+//! Throwaway Postgres databases for the tests and benchmarks, created from
+//! `create_db.sql` on the server `TEST_DATABASE_URL` points to.
+
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio_postgres::{Client, Config, NoTls};
+
+/// A database of its own for a test.
+#[derive(Debug)]
+pub struct TestDb {
+    admin: Client,
+    name: String,
+    /// The URL of the database, for code under test to connect to.
+    pub url: String,
+    pub client: Client,
+}
+
+impl TestDb {
+    /// Panics if `TEST_DATABASE_URL` isn't set, so the tests that need it
+    /// should be `#[ignore]`d.
+    pub async fn create() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let url = env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL must point to a Postgres server to run this test");
+        let mut config: Config = url.parse().expect("invalid TEST_DATABASE_URL");
+        let admin = connect(&config).await;
+
+        let name = format!(
+            "distance_db_test_{}_{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        admin
+            .batch_execute(&format!("CREATE DATABASE {name}"))
+            .await
+            .unwrap();
+        config.dbname(&name);
+        let client = connect(&config).await;
+        client.batch_execute(schema()).await.unwrap();
+
+        TestDb {
+            admin,
+            url: with_dbname(&url, &name),
+            name,
+            client,
+        }
+    }
+
+    /// Drops the database. Databases of failed tests are left behind to be
+    /// inspected.
+    pub async fn destroy(self) {
+        drop(self.client);
+        self.admin
+            .batch_execute(&format!("DROP DATABASE {} WITH (FORCE)", self.name))
+            .await
+            .unwrap();
+    }
+}
+
+/// Connects to the database `config` describes, panicking on failure.
+pub async fn connect(config: &Config) -> Client {
+    let (client, connection) = config.connect(NoTls).await.unwrap();
+    tokio::spawn(connection);
+
+    client
+}
+
+/// The tables and views of `create_db.sql`, without the grants to the reader
+/// role, which may not exist on the test server.
+pub fn schema() -> &'static str {
+    let sql = include_str!("../../create_db.sql");
+    &sql[..sql.find("REVOKE").unwrap_or(sql.len())]
+}
+
+/// Returns the connection string `url` with its database name replaced by
+/// `name`.
+fn with_dbname(url: &str, name: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        // A key=value connection string, in which later keys win
+        return format!("{url} dbname={name}");
+    };
+    let (rest, params) = rest.split_once('?').unwrap_or((rest, ""));
+    let host = rest.split_once('/').map_or(rest, |(host, _)| host);
+    let params = if params.is_empty() {
+        String::new()
+    } else {
+        format!("?{params}")
+    };
+
+    format!("{scheme}://{host}/{name}{params}")
+}